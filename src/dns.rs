@@ -0,0 +1,257 @@
+use crate::socket::new_socket;
+use crate::{CheckJitterError, Pinger, Reply, SocketOptions};
+use log::debug;
+use socket2::{Protocol, Type};
+use std::fmt;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+const HEADER_SIZE: usize = 12;
+const FLAG_RESPONSE: u16 = 0x8000;
+const FLAG_RECURSION_DESIRED: u16 = 0x0100;
+const CLASS_IN: u16 = 1;
+
+const RECORD_TYPES: [(&str, u16); 10] = [
+    ("A", 1),
+    ("NS", 2),
+    ("CNAME", 5),
+    ("SOA", 6),
+    ("PTR", 12),
+    ("MX", 15),
+    ("TXT", 16),
+    ("AAAA", 28),
+    ("SRV", 33),
+    ("ANY", 255),
+];
+
+/// The type of record to query for, e.g. `A` or `AAAA`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RecordType(pub u16);
+
+impl FromStr for RecordType {
+    type Err = String;
+
+    /// Parse a record type by name, e.g. `AAAA`, or by number, e.g. `TYPE28` or `28`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let upper = s.to_uppercase();
+        if let Some((_, value)) = RECORD_TYPES.iter().find(|(name, _)| *name == upper) {
+            return Ok(RecordType(*value));
+        }
+        match upper.strip_prefix("TYPE").unwrap_or(&upper).parse() {
+            Ok(value) => Ok(RecordType(value)),
+            Err(_) => Err(format!("'{}' is not a valid DNS record type", s)),
+        }
+    }
+}
+
+impl fmt::Display for RecordType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match RECORD_TYPES.iter().find(|(_, value)| *value == self.0) {
+            Some((name, _)) => write!(f, "{}", name),
+            None => write!(f, "TYPE{}", self.0),
+        }
+    }
+}
+
+/// Encode a name as a sequence of length-prefixed labels.
+fn encode_name(name: &str) -> Result<Vec<u8>, CheckJitterError> {
+    let mut buf = Vec::with_capacity(name.len() + 2);
+    // The root name has no labels, only the terminating zero length.
+    if name != "." {
+        for label in name.trim_end_matches('.').split('.') {
+            if label.is_empty() || label.len() > 63 {
+                return Err(CheckJitterError::InvalidDnsName(name.to_string()));
+            }
+            buf.push(label.len() as u8);
+            buf.extend_from_slice(label.as_bytes());
+        }
+    }
+    buf.push(0);
+    if buf.len() > 255 {
+        return Err(CheckJitterError::InvalidDnsName(name.to_string()));
+    }
+    Ok(buf)
+}
+
+fn encode_query(id: u16, name: &[u8], record_type: RecordType) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(HEADER_SIZE + name.len() + 4);
+    buf.extend_from_slice(&id.to_be_bytes());
+    buf.extend_from_slice(&FLAG_RECURSION_DESIRED.to_be_bytes());
+    // One question, no answer, authority or additional records.
+    buf.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    buf.extend_from_slice(name);
+    buf.extend_from_slice(&record_type.0.to_be_bytes());
+    buf.extend_from_slice(&CLASS_IN.to_be_bytes());
+    buf
+}
+
+/// Decode the ID and response code of a response, or `None` if it is not a response.
+fn decode_response(buf: &[u8]) -> Option<(u16, u8)> {
+    if buf.len() < HEADER_SIZE {
+        return None;
+    }
+    let id = u16::from_be_bytes([buf[0], buf[1]]);
+    let flags = u16::from_be_bytes([buf[2], buf[3]]);
+    if flags & FLAG_RESPONSE == 0 {
+        return None;
+    }
+    Some((id, (flags & 0x000f) as u8))
+}
+
+/// A series of DNS queries over UDP to a single server, one per probe.
+pub(crate) struct DnsQuery {
+    socket: UdpSocket,
+    name: Vec<u8>,
+    record_type: RecordType,
+}
+
+impl DnsQuery {
+    pub(crate) fn connect(
+        addr: SocketAddr,
+        name: &str,
+        record_type: RecordType,
+        options: &SocketOptions,
+    ) -> Result<Self, CheckJitterError> {
+        let name = encode_name(name)?;
+        let socket: UdpSocket =
+            new_socket(addr.ip(), Type::DGRAM, Protocol::UDP, options, true)?.into();
+        socket.connect(addr)?;
+
+        Ok(DnsQuery {
+            socket,
+            name,
+            record_type,
+        })
+    }
+}
+
+impl Pinger for DnsQuery {
+    /// Send the next query and wait for its response.
+    ///
+    /// Every query uses a random ID, so that late responses to earlier queries are discarded.
+    /// Any response counts as received, whatever its response code.
+    fn probe(&mut self, timeout: Duration) -> Result<Option<Reply>, CheckJitterError> {
+        let id: u16 = rand::random();
+        let query = encode_query(id, &self.name, self.record_type);
+        let mut buf = [0; 4096];
+
+        let start_time = Instant::now();
+        self.socket.send(&query)?;
+
+        loop {
+            let remaining = match timeout.checked_sub(start_time.elapsed()) {
+                Some(remaining) if !remaining.is_zero() => remaining,
+                _ => return Ok(None),
+            };
+            self.socket.set_read_timeout(Some(remaining))?;
+
+            match self.socket.recv(&mut buf) {
+                Ok(n) => match decode_response(&buf[..n]) {
+                    Some((response_id, rcode)) if response_id == id => {
+                        let duration = Instant::now() - start_time;
+                        debug!("DNS response with response code {}", rcode);
+                        return Ok(Some(Reply::from(duration)));
+                    }
+                    Some((response_id, _)) => debug!(
+                        "Discarding DNS response with ID {}, expected {}",
+                        response_id, id
+                    ),
+                    None => debug!("Discarding unrecognized reply of {} bytes", n),
+                },
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    return Ok(None)
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod dns_query_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::net::Ipv4Addr;
+    use std::thread;
+
+    /// Answer every query with an empty response.
+    fn spawn_stub_server() -> (SocketAddr, thread::JoinHandle<Vec<u8>>) {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = socket.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let mut buf = [0; 512];
+            let mut first = None;
+            for _ in 0..3 {
+                let (n, peer) = socket.recv_from(&mut buf).unwrap();
+                first.get_or_insert_with(|| buf[..n].to_vec());
+                buf[2] |= 0x80;
+                socket.send_to(&buf[..n], peer).unwrap();
+            }
+            first.unwrap()
+        });
+        (addr, handle)
+    }
+
+    #[test]
+    fn test_record_type() {
+        assert_eq!("aaaa".parse(), Ok(RecordType(28)));
+        assert_eq!("TYPE65".parse(), Ok(RecordType(65)));
+        assert_eq!("65".parse(), Ok(RecordType(65)));
+        assert!("AAA".parse::<RecordType>().is_err());
+        assert_eq!(RecordType(28).to_string(), "AAAA");
+        assert_eq!(RecordType(65).to_string(), "TYPE65");
+    }
+
+    #[test]
+    fn test_encode_name() {
+        assert_eq!(
+            encode_name("example.com.").unwrap(),
+            b"\x07example\x03com\x00".to_vec()
+        );
+        assert_eq!(encode_name(".").unwrap(), b"\x00".to_vec());
+        assert_eq!(
+            encode_name(""),
+            Err(CheckJitterError::InvalidDnsName("".to_string()))
+        );
+        assert_eq!(
+            encode_name("example..com"),
+            Err(CheckJitterError::InvalidDnsName("example..com".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_decode_response() {
+        let query = encode_query(42, b"\x00", RecordType(1));
+        let mut response = query.clone();
+        response[2] |= 0x80;
+        response[3] |= 0x03;
+
+        assert_eq!(decode_response(&query), None);
+        assert_eq!(decode_response(&response), Some((42, 3)));
+    }
+
+    #[test]
+    fn test_with_stub_server() {
+        let (addr, handle) = spawn_stub_server();
+        let mut query = DnsQuery::connect(
+            addr,
+            "example.com",
+            RecordType(28),
+            &SocketOptions::default(),
+        )
+        .unwrap();
+
+        for _ in 0..3 {
+            let duration = query.probe(Duration::from_secs(1)).unwrap();
+            assert!(duration.is_some());
+        }
+
+        let first = handle.join().unwrap();
+        assert_eq!(
+            &first[HEADER_SIZE..],
+            b"\x07example\x03com\x00\x00\x1c\x00\x01"
+        );
+    }
+}
@@ -0,0 +1,375 @@
+use crate::socket::new_socket;
+use crate::{CheckJitterError, Component, Pinger, Reply, SocketOptions};
+use log::debug;
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use socket2::{Protocol, Type};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use url::Url;
+
+fn tls_config() -> Result<Arc<ClientConfig>, CheckJitterError> {
+    let mut roots = RootCertStore::empty();
+    let native = rustls_native_certs::load_native_certs();
+    for error in native.errors {
+        debug!("Failed to load a native certificate: {}", error);
+    }
+    let (added, ignored) = roots.add_parsable_certificates(native.certs);
+    debug!("Loaded {} native certificates, ignored {}", added, ignored);
+
+    let config =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|e| CheckJitterError::TlsError(e.to_string()))?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+    Ok(Arc::new(config))
+}
+
+/// The time left of `timeout` since `start_time`, or `None` if it has passed.
+fn remaining(start_time: Instant, timeout: Duration) -> Option<Duration> {
+    match timeout.checked_sub(start_time.elapsed()) {
+        Some(remaining) if !remaining.is_zero() => Some(remaining),
+        _ => None,
+    }
+}
+
+/// The status code of an HTTP response status line, such as `HTTP/1.1 200 OK`.
+fn status_code(status_line: &str) -> Option<u16> {
+    let mut parts = status_line.split_whitespace();
+    if !parts.next()?.starts_with("HTTP/") {
+        return None;
+    }
+    let code = parts.next()?;
+    if code.len() != 3 {
+        return None;
+    }
+    code.parse().ok()
+}
+
+/// Send the request and wait for the first line of the response, which is returned.
+fn send_request<S: Read + Write>(stream: &mut S, request: &[u8]) -> io::Result<(Duration, String)> {
+    stream.write_all(request)?;
+    stream.flush()?;
+    let start_time = Instant::now();
+
+    let mut buf = [0; 1024];
+    let n = stream.read(&mut buf)?;
+    let ttfb = start_time.elapsed();
+    if n == 0 {
+        return Err(io::Error::new(
+            ErrorKind::UnexpectedEof,
+            "connection closed before the response",
+        ));
+    }
+    let status_line = buf[..n].split(|b| *b == b'\n').next().unwrap_or_default();
+    let status_line = String::from_utf8_lossy(status_line).trim_end().to_string();
+    debug!("Response: {}", status_line);
+
+    Ok((ttfb, status_line))
+}
+
+/// A series of HTTP(S) GET requests to a URL, using a new connection for every probe.
+pub(crate) struct HttpProbe {
+    host: String,
+    addr: SocketAddr,
+    request: Vec<u8>,
+    tls: Option<(Arc<ClientConfig>, ServerName<'static>)>,
+    options: SocketOptions,
+}
+
+impl HttpProbe {
+    /// Send the requests for `url` to `ip`, one of the addresses its host resolves to.
+    pub(crate) fn new(
+        url: &Url,
+        ip: IpAddr,
+        options: &SocketOptions,
+    ) -> Result<Self, CheckJitterError> {
+        let host = match url.host_str() {
+            Some(host) => host.trim_start_matches('[').trim_end_matches(']'),
+            None => return Err(CheckJitterError::UnsupportedUrl(url.to_string())),
+        };
+        let tls = match url.scheme() {
+            "http" => None,
+            "https" => {
+                let server_name = ServerName::try_from(host.to_string())
+                    .map_err(|e| CheckJitterError::TlsError(e.to_string()))?;
+                Some((tls_config()?, server_name))
+            }
+            _ => return Err(CheckJitterError::UnsupportedUrl(url.to_string())),
+        };
+        // Checked by the scheme above, both of which have a default port.
+        let port = url.port_or_known_default().unwrap_or_default();
+
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: check_jitter/{}\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+            &url[url::Position::BeforePath..url::Position::AfterQuery],
+            &url[url::Position::BeforeHost..url::Position::AfterPort],
+            env!("CARGO_PKG_VERSION"),
+        );
+
+        Ok(HttpProbe {
+            host: host.to_string(),
+            addr: SocketAddr::new(ip, port),
+            request: request.into_bytes(),
+            tls,
+            options: options.clone(),
+        })
+    }
+}
+
+impl Pinger for HttpProbe {
+    /// Resolve the host, connect, complete the TLS handshake if any and wait for the first byte
+    /// of the response, timing each step.
+    ///
+    /// The host is resolved to time the lookup, but the connection is always made to the address
+    /// given to `new`. Steps that time out count the request as lost. A response with a status
+    /// code other than 2xx or 3xx is an error.
+    fn probe(&mut self, timeout: Duration) -> Result<Option<Reply>, CheckJitterError> {
+        let mut components = Vec::with_capacity(4);
+        let start_time = Instant::now();
+
+        let step_start = Instant::now();
+        match (self.host.as_str(), self.addr.port()).to_socket_addrs() {
+            Ok(mut addrs) => {
+                if addrs.next().is_none() {
+                    return Err(CheckJitterError::DnsLookupFailed(self.host.clone()));
+                }
+            }
+            Err(e) => {
+                return Err(CheckJitterError::DnsResolutionError {
+                    addr: self.host.clone(),
+                    error: e.to_string(),
+                })
+            }
+        };
+        components.push((Component::Dns, step_start.elapsed()));
+
+        let Some(left) = remaining(start_time, timeout) else {
+            return Ok(None);
+        };
+        let socket = new_socket(
+            self.addr.ip(),
+            Type::STREAM,
+            Protocol::TCP,
+            &self.options,
+            false,
+        )?;
+        let step_start = Instant::now();
+        let mut tcp: TcpStream = match socket.connect_timeout(&self.addr.into(), left) {
+            Ok(()) => socket.into(),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Ok(None)
+            }
+            Err(e) => return Err(e.into()),
+        };
+        components.push((Component::Connect, step_start.elapsed()));
+
+        let Some(left) = remaining(start_time, timeout) else {
+            return Ok(None);
+        };
+        tcp.set_read_timeout(Some(left))?;
+        tcp.set_write_timeout(Some(left))?;
+
+        let result = match &self.tls {
+            None => send_request(&mut tcp, &self.request),
+            Some((config, server_name)) => {
+                let conn = ClientConnection::new(config.clone(), server_name.clone())
+                    .map_err(|e| CheckJitterError::TlsError(e.to_string()))?;
+                let mut stream = StreamOwned::new(conn, tcp);
+
+                let step_start = Instant::now();
+                while stream.conn.is_handshaking() {
+                    match stream.conn.complete_io(&mut stream.sock) {
+                        Ok(_) => {}
+                        Err(e)
+                            if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+                        {
+                            return Ok(None)
+                        }
+                        Err(e) => return Err(CheckJitterError::TlsError(e.to_string())),
+                    }
+                }
+                components.push((Component::Tls, step_start.elapsed()));
+
+                send_request(&mut stream, &self.request)
+            }
+        };
+
+        let ttfb = match result {
+            Ok((ttfb, status_line)) => match status_code(&status_line) {
+                Some(200..=399) => ttfb,
+                _ => return Err(CheckJitterError::HttpStatus(status_line)),
+            },
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Ok(None)
+            }
+            Err(e) => return Err(e.into()),
+        };
+        components.push((Component::Ttfb, ttfb));
+
+        let rtt = components.iter().map(|(_, d)| *d).sum();
+        debug!("HTTP request timings: {:?}", components);
+
+        Ok(Some(Reply {
+            rtt,
+            components: components
+                .into_iter()
+                .map(|(c, d)| (c, d.as_nanos() as i64))
+                .collect(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod http_probe_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::net::{Ipv4Addr, TcpListener};
+    use std::thread;
+
+    fn spawn_server(requests: usize) -> (Url, thread::JoinHandle<Vec<String>>) {
+        spawn_server_with_response(requests, b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nOK")
+    }
+
+    fn spawn_server_with_response(
+        requests: usize,
+        response: &'static [u8],
+    ) -> (Url, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let url = format!("http://{}/health?full=1", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut received = Vec::new();
+            for _ in 0..requests {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0; 1024];
+                let n = stream.read(&mut buf).unwrap();
+                received.push(String::from_utf8_lossy(&buf[..n]).to_string());
+                stream.write_all(response).unwrap();
+            }
+            received
+        });
+        (url.parse().unwrap(), handle)
+    }
+
+    #[test]
+    fn test_with_server() {
+        let (url, handle) = spawn_server(3);
+        let mut probe = HttpProbe::new(
+            &url,
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            &SocketOptions::default(),
+        )
+        .unwrap();
+
+        for _ in 0..3 {
+            let reply = probe.probe(Duration::from_secs(1)).unwrap().unwrap();
+            let components: Vec<Component> = reply.components.iter().map(|(c, _)| *c).collect();
+            assert_eq!(
+                components,
+                vec![Component::Dns, Component::Connect, Component::Ttfb]
+            );
+        }
+
+        let received = handle.join().unwrap();
+        assert!(received[0].starts_with("GET /health?full=1 HTTP/1.1\r\nHost: 127.0.0.1:"));
+    }
+
+    #[test]
+    fn test_with_hostname() {
+        let (url, handle) = spawn_server(1);
+        let url: Url = url
+            .as_str()
+            .replace("127.0.0.1", "localhost")
+            .parse()
+            .unwrap();
+        // Connects to the given address even if localhost resolves to ::1 first.
+        let mut probe = HttpProbe::new(
+            &url,
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            &SocketOptions::default(),
+        )
+        .unwrap();
+
+        let reply = probe.probe(Duration::from_secs(1)).unwrap();
+        let received = handle.join().unwrap();
+
+        assert!(reply.is_some());
+        assert!(received[0].contains("\r\nHost: localhost:"));
+    }
+
+    #[test]
+    fn test_with_server_error() {
+        let (url, handle) = spawn_server_with_response(
+            1,
+            b"HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\n\r\n",
+        );
+        let mut probe = HttpProbe::new(
+            &url,
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            &SocketOptions::default(),
+        )
+        .unwrap();
+
+        let result = probe.probe(Duration::from_secs(1));
+        handle.join().unwrap();
+
+        assert_eq!(
+            result,
+            Err(CheckJitterError::HttpStatus(
+                "HTTP/1.1 500 Internal Server Error".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_status_code() {
+        assert_eq!(status_code("HTTP/1.1 204 No Content"), Some(204));
+        assert_eq!(status_code("HTTP/1.0 301"), Some(301));
+        assert_eq!(status_code("HTTP/1.1 2000 OK"), None);
+        assert_eq!(status_code("SSH-2.0-OpenSSH_9.6"), None);
+        assert_eq!(status_code(""), None);
+    }
+
+    #[test]
+    fn test_with_slow_server() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let url: Url = format!("http://{}/", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        // Accept the connection but never respond.
+        let handle = thread::spawn(move || listener.accept().unwrap());
+        let mut probe = HttpProbe::new(
+            &url,
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            &SocketOptions::default(),
+        )
+        .unwrap();
+
+        let reply = probe.probe(Duration::from_millis(100)).unwrap();
+        handle.join().unwrap();
+
+        assert_eq!(reply, None);
+    }
+
+    #[test]
+    fn test_with_unsupported_scheme() {
+        let url: Url = "ftp://127.0.0.1/".parse().unwrap();
+
+        let result = HttpProbe::new(
+            &url,
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            &SocketOptions::default(),
+        );
+
+        assert_eq!(
+            result.err(),
+            Some(CheckJitterError::UnsupportedUrl(
+                "ftp://127.0.0.1/".to_string()
+            ))
+        );
+    }
+}
@@ -0,0 +1,574 @@
+use crate::payload::{fill, payload_size};
+use crate::sequence::SequenceTracker;
+use crate::socket::{new_socket, send_error};
+use crate::timestamping;
+use crate::{CheckJitterError, PayloadPattern, Pinger, Reply, SequenceErrors, SocketOptions};
+use log::{debug, info};
+use socket2::{Protocol, SockAddr, Socket, Type};
+use std::io::{self, ErrorKind};
+use std::mem::MaybeUninit;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+const ICMPV4_ECHO_REQUEST: u8 = 8;
+const ICMPV4_ECHO_REPLY: u8 = 0;
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+const ICMPV4_DEST_UNREACHABLE: u8 = 3;
+const ICMPV4_TIME_EXCEEDED: u8 = 11;
+const ICMPV6_DEST_UNREACHABLE: u8 = 1;
+const ICMPV6_TIME_EXCEEDED: u8 = 3;
+const HEADER_SIZE: usize = 8;
+const IPV6_HEADER_SIZE: usize = 40;
+const PAYLOAD_SIZE: usize = 24;
+/// Room for the IPv4 header, including options, that precedes replies on some sockets.
+const MAX_IP_HEADER_SIZE: usize = 60;
+
+pub(crate) fn checksum(buf: &[u8]) -> u16 {
+    let mut sum: u32 = buf
+        .chunks(2)
+        .map(|c| u32::from(u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)])))
+        .sum();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn encode_request(ip: IpAddr, ident: u16, seq: u16, payload: &[u8]) -> Vec<u8> {
+    let mut buf = vec![0; HEADER_SIZE + payload.len()];
+    buf[0] = match ip {
+        IpAddr::V4(_) => ICMPV4_ECHO_REQUEST,
+        IpAddr::V6(_) => ICMPV6_ECHO_REQUEST,
+    };
+    buf[4..6].copy_from_slice(&ident.to_be_bytes());
+    buf[6..8].copy_from_slice(&seq.to_be_bytes());
+    buf[HEADER_SIZE..].copy_from_slice(payload);
+    // The kernel calculates the checksum of ICMPv6, which covers a pseudo header of the
+    // addresses, so it is only set for ICMPv4.
+    if ip.is_ipv4() {
+        let sum = checksum(&buf);
+        buf[2..4].copy_from_slice(&sum.to_be_bytes());
+    }
+    buf
+}
+
+/// The error an ICMP error message of type `kind` about a request is reported as, given the
+/// address it was sent from, or `None` if it is not an error message.
+fn error(ip: IpAddr, kind: u8, code: u8, from: IpAddr) -> Option<CheckJitterError> {
+    match (ip, kind) {
+        (IpAddr::V4(_), ICMPV4_TIME_EXCEEDED) | (IpAddr::V6(_), ICMPV6_TIME_EXCEEDED) => {
+            Some(CheckJitterError::TimeExceeded(from))
+        }
+        (IpAddr::V4(_), ICMPV4_DEST_UNREACHABLE) | (IpAddr::V6(_), ICMPV6_DEST_UNREACHABLE) => {
+            Some(CheckJitterError::DestinationUnreachable(from, code))
+        }
+        _ => None,
+    }
+}
+
+/// Decode the type and code of an ICMP message, and the identifier and sequence number of the
+/// echo message it concerns: the message itself, or the request quoted by an error message.
+///
+/// IPv4 raw sockets, and datagram sockets on some systems, include the IP header, which is
+/// recognized by its version. An ICMP message never starts with that, since there is no ICMP
+/// type 64 to 79.
+fn decode_message(ip: IpAddr, buf: &[u8]) -> Option<(u8, u8, u16, u16)> {
+    let buf = match ip {
+        IpAddr::V4(_) if buf.first()? >> 4 == 4 => buf.get(usize::from(buf[0] & 0x0f) * 4..)?,
+        _ => buf,
+    };
+    if buf.len() < HEADER_SIZE {
+        return None;
+    }
+    let (kind, code) = (buf[0], buf[1]);
+    let echo = match ip {
+        // Error messages quote the IP header and the start of the request that caused them.
+        _ if error(ip, kind, code, ip).is_none() => buf,
+        IpAddr::V4(_) => {
+            let quoted = &buf[HEADER_SIZE..];
+            quoted.get(usize::from(quoted.first()? & 0x0f) * 4..)?
+        }
+        IpAddr::V6(_) => buf.get(HEADER_SIZE + IPV6_HEADER_SIZE..)?,
+    };
+    if echo.len() < HEADER_SIZE {
+        return None;
+    }
+    Some((
+        kind,
+        code,
+        u16::from_be_bytes([echo[4], echo[5]]),
+        u16::from_be_bytes([echo[6], echo[7]]),
+    ))
+}
+
+/// Receive a message into `buf`, returning its length and the address it was sent from.
+fn recv_from(socket: &Socket, buf: &mut [u8]) -> io::Result<(usize, Option<IpAddr>)> {
+    // SAFETY: `recv_from` never writes uninitialized bytes to the buffer, so it can be viewed as
+    // a slice of `MaybeUninit<u8>`, which has the same layout as `u8`.
+    let uninit = unsafe { &mut *(buf as *mut [u8] as *mut [MaybeUninit<u8>]) };
+    let (n, from) = socket.recv_from(uninit)?;
+    Ok((n, from.as_socket().map(|addr| addr.ip())))
+}
+
+/// The requests of a fixed rate stream, which have consecutive sequence numbers starting at
+/// `first_seq`, and their replies.
+#[derive(Debug)]
+struct InFlight {
+    first_seq: u16,
+    samples: u8,
+    send_times: Vec<Instant>,
+    replies: Vec<Option<Reply>>,
+}
+
+impl InFlight {
+    fn new(first_seq: u16, samples: u8) -> Self {
+        InFlight {
+            first_seq,
+            samples,
+            send_times: Vec::with_capacity(samples.into()),
+            replies: vec![None; samples.into()],
+        }
+    }
+
+    /// The time to send the next request at, or `None` if every request has been sent.
+    fn next_send(&self, start_time: Instant, interval: Duration) -> Option<Instant> {
+        let sent = self.send_times.len() as u32;
+        (sent < u32::from(self.samples)).then(|| start_time + interval * sent)
+    }
+
+    fn sent(&mut self, at: Instant) {
+        self.send_times.push(at);
+    }
+
+    fn index(&self, seq: u16) -> Option<usize> {
+        let index = usize::from(seq.wrapping_sub(self.first_seq));
+        (index < self.send_times.len()).then_some(index)
+    }
+
+    fn contains(&self, seq: u16) -> bool {
+        self.index(seq).is_some()
+    }
+
+    /// Record the reply to the request with sequence number `seq`, unless it is not one of the
+    /// requests, was already replied to or arrived after `timeout`.
+    fn received(&mut self, seq: u16, at: Instant, timeout: Duration) -> bool {
+        let Some(index) = self.index(seq) else {
+            return false;
+        };
+        let rtt = at - self.send_times[index];
+        if self.replies[index].is_some() || rtt > timeout {
+            return false;
+        }
+        self.replies[index] = Some(Reply::from(rtt));
+        true
+    }
+
+    /// Whether every request has been sent and replied to.
+    fn is_complete(&self) -> bool {
+        self.send_times.len() == usize::from(self.samples)
+            && self.replies.iter().all(Option::is_some)
+    }
+
+    /// The time after which the reply to the last request is counted as lost.
+    fn last_deadline(&self, timeout: Duration) -> Option<Instant> {
+        self.send_times.last().map(|sent| *sent + timeout)
+    }
+
+    /// The replies to the requests sent, leaving out the requests that were neither replied to
+    /// nor timed out at `end`.
+    fn replies(&self, timeout: Duration, end: Instant) -> Vec<Option<Reply>> {
+        self.send_times
+            .iter()
+            .zip(&self.replies)
+            .filter(|(sent, reply)| reply.is_some() || **sent + timeout <= end)
+            .map(|(_, reply)| reply.clone())
+            .collect()
+    }
+}
+
+/// A series of ICMP echo requests to a single host, one per probe.
+pub struct IcmpEcho {
+    socket: Socket,
+    ip: IpAddr,
+    dest: SockAddr,
+    /// Datagram sockets on Linux replace the identifier with their own, so it is only
+    /// matched on raw sockets.
+    match_ident: bool,
+    ident: u16,
+    seq: u16,
+    payload_size: usize,
+    payload_pattern: Option<PayloadPattern>,
+    kernel_timestamps: bool,
+    tracker: SequenceTracker,
+}
+
+impl IcmpEcho {
+    /// Send the echo requests over a raw socket, which requires root or `CAP_NET_RAW`.
+    pub fn raw(ip: IpAddr, options: &SocketOptions) -> Result<Self, CheckJitterError> {
+        Self::connect(ip, Type::RAW, options)
+    }
+
+    /// Send the echo requests over a datagram socket, which on Linux requires the group of the
+    /// process to be in `net.ipv4.ping_group_range`.
+    pub fn datagram(ip: IpAddr, options: &SocketOptions) -> Result<Self, CheckJitterError> {
+        Self::connect(ip, Type::DGRAM, options)
+    }
+
+    fn connect(ip: IpAddr, ty: Type, options: &SocketOptions) -> Result<Self, CheckJitterError> {
+        let protocol = match ip {
+            IpAddr::V4(_) => Protocol::ICMPV4,
+            IpAddr::V6(_) => Protocol::ICMPV6,
+        };
+        let socket = new_socket(ip, ty, protocol, options, false)?;
+        if options.kernel_timestamps {
+            timestamping::enable(&socket)?;
+        }
+
+        Ok(IcmpEcho {
+            socket,
+            ip,
+            dest: SocketAddr::new(ip, 0).into(),
+            match_ident: ty == Type::RAW,
+            ident: rand::random(),
+            seq: 0,
+            payload_size: payload_size(options, 0, PAYLOAD_SIZE)?,
+            payload_pattern: options.payload_pattern.clone(),
+            kernel_timestamps: options.kernel_timestamps,
+            tracker: SequenceTracker::default(),
+        })
+    }
+
+    /// Send an echo request with the next sequence number.
+    fn send_next(&mut self) -> Result<(), CheckJitterError> {
+        self.seq = self.seq.wrapping_add(1);
+        let mut payload = vec![0; self.payload_size];
+        fill(&mut payload, self.payload_pattern.as_ref());
+        let request = encode_request(self.ip, self.ident, self.seq, &payload);
+        self.socket
+            .send_to(&request, &self.dest)
+            .map_err(|e| send_error(e, request.len()))?;
+        self.tracker.sent();
+        Ok(())
+    }
+
+    /// Record a reply to the request with sequence number `seq`, where the first request sent
+    /// has sequence number 1.
+    fn track(&mut self, seq: u16) {
+        self.tracker.received(usize::from(seq.wrapping_sub(1)));
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> io::Result<(usize, Option<IpAddr>, Option<Duration>)> {
+        if self.kernel_timestamps {
+            timestamping::recv(&self.socket, buf)
+        } else {
+            recv_from(&self.socket, buf).map(|(n, from)| (n, from, None))
+        }
+    }
+
+    fn recv_buffer(&self) -> Vec<u8> {
+        vec![0; MAX_IP_HEADER_SIZE + HEADER_SIZE + self.payload_size]
+    }
+
+    fn reply_type(&self) -> u8 {
+        match self.ip {
+            IpAddr::V4(_) => ICMPV4_ECHO_REPLY,
+            IpAddr::V6(_) => ICMPV6_ECHO_REPLY,
+        }
+    }
+
+    /// Whether a message with the identifier `ident` concerns our own requests.
+    fn is_own(&self, ident: u16) -> bool {
+        ident == self.ident || !self.match_ident
+    }
+
+    /// The round trip time between the kernel timestamps of the request and its reply, or `rtt`
+    /// as measured in user space if either is missing.
+    fn kernel_rtt(&self, received: Option<Duration>, rtt: Duration) -> Duration {
+        match (timestamping::last_sent(&self.socket), received) {
+            (Some(sent), Some(received)) if received >= sent => {
+                let kernel_rtt = received - sent;
+                debug!(
+                    "Kernel RTT: {:?}, user-space RTT: {:?}, difference: {:?}",
+                    kernel_rtt,
+                    rtt,
+                    rtt.saturating_sub(kernel_rtt)
+                );
+                kernel_rtt
+            }
+            _ => {
+                debug!(
+                    "Kernel timestamps missing, using the user-space RTT: {:?}",
+                    rtt
+                );
+                rtt
+            }
+        }
+    }
+}
+
+impl Pinger for IcmpEcho {
+    /// Send the next echo request and wait for its reply.
+    ///
+    /// Replies to earlier requests that arrive while waiting were already counted as lost and
+    /// are discarded, as are unrelated ICMP messages seen by raw sockets. Raw sockets also see
+    /// time exceeded and destination unreachable messages about the request, which are returned
+    /// as errors.
+    fn probe(&mut self, timeout: Duration) -> Result<Option<Reply>, CheckJitterError> {
+        let mut buf = self.recv_buffer();
+
+        if self.kernel_timestamps {
+            // Discard the timestamps of earlier requests that were lost.
+            timestamping::last_sent(&self.socket);
+        }
+
+        let start_time = Instant::now();
+        self.send_next()?;
+
+        loop {
+            let remaining = match timeout.checked_sub(start_time.elapsed()) {
+                Some(remaining) if !remaining.is_zero() => remaining,
+                _ => return Ok(None),
+            };
+            self.socket.set_read_timeout(Some(remaining))?;
+
+            match self.recv(&mut buf) {
+                Ok((n, from, received_at)) => match decode_message(self.ip, &buf[..n]) {
+                    Some((kind, code, ident, seq)) if seq == self.seq && self.is_own(ident) => {
+                        if kind == self.reply_type() {
+                            let mut rtt = Instant::now() - start_time;
+                            if self.kernel_timestamps {
+                                rtt = self.kernel_rtt(received_at, rtt);
+                            }
+                            self.track(seq);
+                            return Ok(Some(Reply::from(rtt)));
+                        }
+                        match error(self.ip, kind, code, from.unwrap_or(self.ip)) {
+                            Some(e) => return Err(e),
+                            // Raw sockets see our own requests on the loopback interface.
+                            None => debug!("Discarding ICMP message of type {}", kind),
+                        }
+                    }
+                    Some((kind, _, ident, seq))
+                        if kind == self.reply_type() && self.is_own(ident) =>
+                    {
+                        debug!(
+                            "Discarding echo reply with sequence number {}, expected {}",
+                            seq, self.seq
+                        );
+                        self.track(seq);
+                    }
+                    _ => debug!("Discarding unrelated ICMP message of {} bytes", n),
+                },
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    return Ok(None)
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Send an echo request every `interval`, each with its own sequence number, and match the
+    /// replies to the requests as they arrive.
+    ///
+    /// Replies that arrive later than `timeout` after their request are counted as lost, as in
+    /// stop-and-wait mode. The times are measured in user space.
+    fn probe_at_rate(
+        &mut self,
+        samples: u8,
+        interval: Duration,
+        timeout: Duration,
+        deadline: Option<Instant>,
+    ) -> Result<Vec<Option<Reply>>, CheckJitterError> {
+        let mut in_flight = InFlight::new(self.seq.wrapping_add(1), samples);
+        let mut buf = self.recv_buffer();
+        let start_time = Instant::now();
+
+        loop {
+            let now = Instant::now();
+            let next_send = in_flight
+                .next_send(start_time, interval)
+                .filter(|send_time| deadline.map_or(true, |d| *send_time < d));
+            let wake_time = match next_send {
+                Some(send_time) if send_time <= now => {
+                    self.send_next()?;
+                    in_flight.sent(Instant::now());
+                    continue;
+                }
+                Some(send_time) => send_time,
+                None if in_flight.is_complete() => break,
+                None => match in_flight.last_deadline(timeout) {
+                    Some(end) => match deadline {
+                        Some(d) if d.min(end) > now => d.min(end),
+                        None if end > now => end,
+                        _ => break,
+                    },
+                    None => break,
+                },
+            };
+            self.socket.set_read_timeout(Some(wake_time - now))?;
+
+            match self.recv(&mut buf) {
+                Ok((n, from, _)) => match decode_message(self.ip, &buf[..n]) {
+                    Some((kind, code, ident, seq)) if self.is_own(ident) => {
+                        if kind == self.reply_type() {
+                            self.track(seq);
+                            if !in_flight.received(seq, Instant::now(), timeout) {
+                                debug!("Discarding echo reply with sequence number {}", seq);
+                            }
+                        } else if let Some(e) = error(self.ip, kind, code, from.unwrap_or(self.ip))
+                        {
+                            if in_flight.contains(seq) {
+                                return Err(e);
+                            }
+                        }
+                    }
+                    _ => debug!("Discarding unrelated ICMP message of {} bytes", n),
+                },
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        debug!("Sent {} pings in {:?}", samples, start_time.elapsed());
+        let replies = in_flight.replies(timeout, Instant::now());
+        if replies.len() < usize::from(samples) {
+            info!(
+                "Deadline reached after {} of {} samples",
+                replies.len(),
+                samples
+            );
+        }
+        Ok(replies)
+    }
+
+    fn sequence_errors(&self) -> Option<SequenceErrors> {
+        Some(self.tracker.errors())
+    }
+}
+
+#[cfg(test)]
+mod icmp_echo_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn test_checksum() {
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let buf = encode_request(ip, 0x1234, 1, &[1, 2, 3]);
+
+        assert_eq!(checksum(&buf), 0);
+    }
+
+    #[test]
+    fn test_decode_with_ip_header() {
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let mut packet = vec![0x45];
+        packet.extend_from_slice(&[0; 19]);
+        packet.extend_from_slice(&encode_request(ip, 7, 3, &[0; 4]));
+        packet[20] = ICMPV4_ECHO_REPLY;
+
+        assert_eq!(
+            decode_message(ip, &packet),
+            Some((ICMPV4_ECHO_REPLY, 0, 7, 3))
+        );
+    }
+
+    #[test]
+    fn test_decode_without_ip_header() {
+        let ip = IpAddr::V6(Ipv6Addr::LOCALHOST);
+        let mut message = encode_request(ip, 7, 3, &[0; 4]);
+        message[0] = ICMPV6_ECHO_REPLY;
+
+        assert_eq!(
+            decode_message(ip, &message),
+            Some((ICMPV6_ECHO_REPLY, 0, 7, 3))
+        );
+        assert_eq!(decode_message(ip, &message[..4]), None);
+    }
+
+    #[test]
+    fn test_decode_time_exceeded() {
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let mut packet = vec![0x45];
+        packet.extend_from_slice(&[0; 19]);
+        packet.extend_from_slice(&[ICMPV4_TIME_EXCEEDED, 0, 0, 0, 0, 0, 0, 0]);
+        packet.push(0x45);
+        packet.extend_from_slice(&[0; 19]);
+        packet.extend_from_slice(&encode_request(ip, 7, 3, &[])[..HEADER_SIZE]);
+
+        assert_eq!(
+            decode_message(ip, &packet),
+            Some((ICMPV4_TIME_EXCEEDED, 0, 7, 3))
+        );
+        assert_eq!(decode_message(ip, &packet[..40]), None);
+    }
+
+    #[test]
+    fn test_decode_unreachable() {
+        let ip = IpAddr::V6(Ipv6Addr::LOCALHOST);
+        let mut message = vec![ICMPV6_DEST_UNREACHABLE, 3, 0, 0, 0, 0, 0, 0];
+        message.extend_from_slice(&[0; IPV6_HEADER_SIZE]);
+        message.extend_from_slice(&encode_request(ip, 7, 3, &[0; 4]));
+
+        assert_eq!(
+            decode_message(ip, &message),
+            Some((ICMPV6_DEST_UNREACHABLE, 3, 7, 3))
+        );
+    }
+
+    #[test]
+    fn test_in_flight() {
+        let start_time = Instant::now();
+        let interval = Duration::from_millis(20);
+        let timeout = Duration::from_millis(100);
+        let mut in_flight = InFlight::new(u16::MAX, 3);
+
+        for i in 0..3 {
+            let send_time = in_flight.next_send(start_time, interval).unwrap();
+            assert_eq!(send_time, start_time + interval * i);
+            in_flight.sent(send_time);
+        }
+        assert_eq!(in_flight.next_send(start_time, interval), None);
+
+        // The sequence numbers wrap around from the first request to the second.
+        assert!(in_flight.received(0, start_time + Duration::from_millis(25), timeout));
+        assert!(!in_flight.received(0, start_time + Duration::from_millis(26), timeout));
+        assert!(!in_flight.received(2, start_time, timeout));
+        assert!(!in_flight.received(1, start_time + Duration::from_millis(141), timeout));
+        assert!(in_flight.received(u16::MAX, start_time + Duration::from_millis(30), timeout));
+        assert!(!in_flight.is_complete());
+        assert_eq!(
+            in_flight.last_deadline(timeout),
+            Some(start_time + Duration::from_millis(140))
+        );
+
+        let expected = vec![
+            Some(Reply::from(Duration::from_millis(30))),
+            Some(Reply::from(Duration::from_millis(5))),
+            None,
+        ];
+        let end = start_time + Duration::from_millis(140);
+        assert_eq!(in_flight.replies(timeout, end), expected);
+        // The last request had neither been replied to nor timed out yet.
+        let end = start_time + Duration::from_millis(130);
+        assert_eq!(in_flight.replies(timeout, end), expected[..2]);
+    }
+
+    #[test]
+    fn test_error() {
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let from = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+        assert_eq!(
+            error(ip, ICMPV4_TIME_EXCEEDED, 0, from),
+            Some(CheckJitterError::TimeExceeded(from))
+        );
+        assert_eq!(
+            error(ip, ICMPV4_DEST_UNREACHABLE, 1, from),
+            Some(CheckJitterError::DestinationUnreachable(from, 1))
+        );
+        assert_eq!(error(ip, ICMPV4_ECHO_REPLY, 0, from), None);
+    }
+}
@@ -1,16 +1,56 @@
-use log::{debug, error, info};
+use log::{debug, info};
 use nagios_range::Error as RangeError;
 use nagios_range::NagiosRange as ThresholdRange;
 use rand::Rng;
 use std::fmt;
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, ToSocketAddrs};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
 use std::time::{Duration, Instant};
 use thiserror::Error;
 
-#[derive(Debug)]
+mod dns;
+mod http;
+mod icmp;
+mod payload;
+mod pinger;
+mod sequence;
+mod socket;
+mod tcp;
+mod timestamp;
+mod timestamping;
+mod twamp;
+mod udp;
+
+pub use dns::RecordType;
+pub use icmp::IcmpEcho;
+pub use payload::PayloadPattern;
+#[cfg(test)]
+use pinger::MockPinger;
+pub use pinger::Pinger;
+pub use sequence::SequenceErrors;
+pub use socket::Dscp;
+pub use twamp::run_twamp_reflector;
+pub use udp::run_udp_responder;
+
+#[derive(Clone, Debug)]
 pub enum SocketType {
     Datagram,
     Raw,
+    /// Measure the time of a TCP handshake to the given port instead of sending ICMP pings.
+    Tcp(u16),
+    /// Send sequenced UDP datagrams to an echo service (RFC 862) on the given port.
+    Udp(u16),
+    /// Send TWAMP-Light (RFC 5357, appendix I) test packets to a reflector on the given port.
+    Twamp(u16),
+    /// Send ICMP timestamp requests (type 13) over a raw socket. IPv4 only.
+    Timestamp,
+    /// Send HTTP(S) GET requests to the given URL, timing each step of the request.
+    Http(url::Url),
+    /// Send DNS queries over UDP to a server on the given port.
+    Dns {
+        port: u16,
+        name: String,
+        record_type: RecordType,
+    },
 }
 
 impl fmt::Display for SocketType {
@@ -18,16 +58,79 @@ impl fmt::Display for SocketType {
         match self {
             SocketType::Datagram => write!(f, "Datagram"),
             SocketType::Raw => write!(f, "Raw"),
+            SocketType::Tcp(port) => write!(f, "TCP port {}", port),
+            SocketType::Udp(port) => write!(f, "UDP port {}", port),
+            SocketType::Twamp(port) => write!(f, "TWAMP-Light port {}", port),
+            SocketType::Timestamp => write!(f, "ICMP Timestamp"),
+            SocketType::Http(url) => write!(f, "HTTP {}", url),
+            SocketType::Dns {
+                port,
+                name,
+                record_type,
+            } => write!(f, "DNS {} {} on port {}", name, record_type, port),
         }
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// Options for the probes and the sockets that send them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SocketOptions {
+    /// The local address to send the probes from.
+    pub source: Option<IpAddr>,
+    /// The network interface to send the probes through (`SO_BINDTODEVICE`). Linux only.
+    pub interface: Option<String>,
+    /// The DSCP marking of the probes, set in the TOS byte (IPv4) or traffic class (IPv6).
+    pub dscp: Option<Dscp>,
+    /// The size in bytes of the payload of ICMP echo requests, UDP datagrams or TWAMP-Light test
+    /// packets, instead of the default size of the mode.
+    pub payload_size: Option<usize>,
+    /// The pattern to fill the payload with instead of random bytes.
+    pub payload_pattern: Option<PayloadPattern>,
+    /// Set the Don't Fragment bit (IPv4) or disable fragmentation (IPv6). Linux only.
+    pub dont_fragment: bool,
+    /// The time to live (IPv4) or hop limit (IPv6) of the probes.
+    pub ttl: Option<u32>,
+    /// Take the send and receive times of ICMP echo probes from the kernel (`SO_TIMESTAMPING`)
+    /// instead of measuring them in user space, which excludes scheduling delays. Hardware
+    /// timestamps of the network card are used if it takes them, software timestamps otherwise.
+    /// Linux only.
+    pub kernel_timestamps: bool,
+    /// Send a probe every interval without waiting for the reply to the previous one, instead of
+    /// waiting for a random interval after each reply. ICMP pings only.
+    pub fixed_rate: Option<Duration>,
+    /// Stop probing at this instant and measure the samples collected so far, regardless of the
+    /// timeout and intervals of the remaining probes.
+    pub deadline: Option<Instant>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum AggregationMethod {
     Average,
     Median,
     Max,
     Min,
+    /// The given percentile (0-100) of the deltas, using linear interpolation between the
+    /// closest ranks.
+    Percentile(f64),
+    Rfc3550,
+    /// The standard deviation of the round trip times.
+    StdDev,
+    /// The interquartile range (75th - 25th percentile) of the round trip times.
+    Iqr,
+}
+
+fn parse_percentile(s: &str) -> Option<f64> {
+    let p = s
+        .strip_prefix("percentile:")
+        .or_else(|| s.strip_prefix('p'))?
+        .parse::<f64>()
+        .ok()?;
+
+    if (0.0..=100.0).contains(&p) {
+        Some(p)
+    } else {
+        None
+    }
 }
 
 impl std::str::FromStr for AggregationMethod {
@@ -44,7 +147,14 @@ impl std::str::FromStr for AggregationMethod {
             "min" => Ok(AggregationMethod::Min),
             "maximum" => Ok(AggregationMethod::Max),
             "max" => Ok(AggregationMethod::Max),
-            _ => Err(format!("'{}' is not a valid aggregation method", s)),
+            "rfc3550" => Ok(AggregationMethod::Rfc3550),
+            "stddev" => Ok(AggregationMethod::StdDev),
+            "sd" => Ok(AggregationMethod::StdDev),
+            "iqr" => Ok(AggregationMethod::Iqr),
+            p => match parse_percentile(p) {
+                Some(p) => Ok(AggregationMethod::Percentile(p)),
+                None => Err(format!("'{}' is not a valid aggregation method", s)),
+            },
         }
     }
 }
@@ -56,58 +166,139 @@ impl fmt::Display for AggregationMethod {
             AggregationMethod::Median => write!(f, "Median"),
             AggregationMethod::Max => write!(f, "Max"),
             AggregationMethod::Min => write!(f, "Min"),
+            AggregationMethod::Percentile(p) => write!(f, "P{}", p),
+            AggregationMethod::Rfc3550 => write!(f, "RFC3550"),
+            AggregationMethod::StdDev => write!(f, "StdDev"),
+            AggregationMethod::Iqr => write!(f, "IQR"),
         }
     }
 }
 
-#[derive(Debug)]
-pub struct PingErrorWrapper(ping::Error);
-
-impl PartialEq for PingErrorWrapper {
-    fn eq(&self, other: &Self) -> bool {
-        format!("{:?}", self.0) == format!("{:?}", other.0)
+impl AggregationMethod {
+    /// Whether the method aggregates the deltas between consecutive durations rather than the
+    /// durations themselves.
+    fn uses_deltas(&self) -> bool {
+        !matches!(self, AggregationMethod::StdDev | AggregationMethod::Iqr)
     }
 }
 
-impl Eq for PingErrorWrapper {}
+#[cfg(test)]
+mod aggregation_method_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_percentile() {
+        assert_eq!("p95".parse(), Ok(AggregationMethod::Percentile(95.0)));
+        assert_eq!("P99".parse(), Ok(AggregationMethod::Percentile(99.0)));
+        assert_eq!(
+            "percentile:99.9".parse(),
+            Ok(AggregationMethod::Percentile(99.9))
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_percentile() {
+        assert!("p101".parse::<AggregationMethod>().is_err());
+        assert!("p-1".parse::<AggregationMethod>().is_err());
+        assert!("p".parse::<AggregationMethod>().is_err());
+        assert!("percentile:".parse::<AggregationMethod>().is_err());
+        assert!("percentile:abc".parse::<AggregationMethod>().is_err());
+    }
 
-impl fmt::Display for PingErrorWrapper {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self.0)
+    #[test]
+    fn test_display_percentile() {
+        assert_eq!(AggregationMethod::Percentile(95.0).to_string(), "P95");
+        assert_eq!(AggregationMethod::Percentile(99.9).to_string(), "P99.9");
     }
 }
 
-impl std::error::Error for PingErrorWrapper {}
-
 #[non_exhaustive]
 #[derive(Error, Debug, Eq, PartialEq)]
 pub enum CheckJitterError {
+    #[error("{0}: {1}")]
+    AddressFailed(IpAddr, Box<CheckJitterError>),
+
+    #[error("Binding the probe socket to {0} is not permitted")]
+    BindNotPermitted(String),
+
+    #[error("The deadline passed after {0} samples, at least 2 are required to calculate jitter")]
+    DeadlineExceeded(usize),
+
+    #[error("Destination unreachable reported by {0} (code {1})")]
+    DestinationUnreachable(IpAddr, u8),
+
     #[error("DNS Lookup failed for: {0}")]
     DnsLookupFailed(String),
 
     #[error("DNS resolution error for '{addr}': {error}")]
     DnsResolutionError { addr: String, error: String },
 
+    #[error("Disabling fragmentation is not supported on this platform")]
+    DontFragmentUnsupported,
+
+    #[error("DSCP marking is not supported on this platform: {0}")]
+    DscpUnsupported(Dscp),
+
     #[error("The delta count is 0. Cannot calculate jitter.")]
     EmptyDeltas,
 
+    #[error("Sending probes at a fixed rate is only supported for ICMP pings")]
+    FixedRateUnsupported,
+
+    #[error("HTTP request failed with status: {0}")]
+    HttpStatus(String),
+
     #[error("At least 2 samples are required to calculate jitter, got {0}.")]
     InsufficientSamples(u8),
 
+    #[error("Binding to an interface is not supported on this platform: {0}")]
+    InterfaceBindUnsupported(String),
+
+    #[error("Invalid DNS name: {0}")]
+    InvalidDnsName(String),
+
     #[error("Invalid IP: {0}")]
     InvalidIP(String),
 
+    #[error("Kernel timestamps are not supported on this platform")]
+    KernelTimestampsUnsupported,
+
+    #[error("No {1} address found for: {0}")]
+    NoAddressOfFamily(String, AddressFamily),
+
+    #[error("The probe of {0} bytes is larger than the path allows without fragmentation")]
+    PacketTooLarge(usize),
+
+    #[error("The payload size of {0} bytes is smaller than the {1} bytes the probe requires")]
+    PayloadTooSmall(usize, usize),
+
     #[error("Ping failed because of insufficient permissions")]
     PermissionDenied,
 
-    #[error("Ping failed with error: {0}")]
-    PingError(PingErrorWrapper),
-
     #[error("Ping failed with IO error: {0}")]
     PingIoError(String),
 
-    #[error("Ping timed out after: {0}ms")]
-    Timeout(String),
+    #[error("The source address {0} is not of the same IP version as the target {1}")]
+    SourceAddressMismatch(IpAddr, IpAddr),
+
+    #[error("The source address {0} is not assigned to a local interface")]
+    SourceAddressNotLocal(IpAddr),
+
+    #[error("Time to live exceeded in transit, reported by {0}")]
+    TimeExceeded(IpAddr),
+
+    #[error("ICMP timestamp requests require an IPv4 address, got: {0}")]
+    TimestampRequiresIpv4(String),
+
+    #[error("TLS error: {0}")]
+    TlsError(String),
+
+    #[error("No such network interface: {0}")]
+    UnknownInterface(String),
+
+    #[error("Unsupported URL, expected an http or https URL with a host: {0}")]
+    UnsupportedUrl(String),
 
     #[error("Unable to parse hostname: {0}")]
     UrlParseError(url::ParseError),
@@ -122,12 +313,471 @@ impl From<std::io::Error> for CheckJitterError {
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct Thresholds {
     pub warning: Option<ThresholdRange>,
     pub critical: Option<ThresholdRange>,
 }
 
+/// A part of the round trip that some probe types measure separately.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Component {
+    /// The one-way delay from the plugin to the target.
+    Forward,
+    /// The one-way delay from the target back to the plugin.
+    Reverse,
+    /// The time to resolve the hostname of an HTTP request.
+    Dns,
+    /// The time to establish the TCP connection of an HTTP request.
+    Connect,
+    /// The time to complete the TLS handshake of an HTTPS request.
+    Tls,
+    /// The time from sending an HTTP request until the first byte of the response.
+    Ttfb,
+}
+
+impl Component {
+    const ALL: [Component; 6] = [
+        Component::Forward,
+        Component::Reverse,
+        Component::Dns,
+        Component::Connect,
+        Component::Tls,
+        Component::Ttfb,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Component::Forward => "forward",
+            Component::Reverse => "reverse",
+            Component::Dns => "dns",
+            Component::Connect => "connect",
+            Component::Tls => "tls",
+            Component::Ttfb => "ttfb",
+        }
+    }
+}
+
+impl fmt::Display for Component {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Component::Forward => write!(f, "Forward"),
+            Component::Reverse => write!(f, "Reverse"),
+            Component::Dns => write!(f, "DNS"),
+            Component::Connect => write!(f, "Connect"),
+            Component::Tls => write!(f, "TLS"),
+            Component::Ttfb => write!(f, "TTFB"),
+        }
+    }
+}
+
+/// A value measured by the plugin and reported as performance data.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Metric {
+    Jitter(AggregationMethod),
+    ComponentJitter(Component, AggregationMethod),
+    PacketLoss,
+    RttMin,
+    RttAvg,
+    RttMax,
+    RttMdev,
+    Reordered,
+    Duplicates,
+}
+
+impl Metric {
+    fn uom(&self) -> &'static str {
+        match self {
+            Metric::PacketLoss => "%",
+            Metric::Reordered | Metric::Duplicates => "",
+            _ => "ms",
+        }
+    }
+
+    fn max(&self) -> Option<f64> {
+        match self {
+            Metric::PacketLoss => Some(100.0),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Metric::Jitter(m) => write!(f, "{} Jitter", m),
+            Metric::ComponentJitter(c, m) => write!(f, "{} {} Jitter", c, m),
+            Metric::PacketLoss => write!(f, "Packet Loss"),
+            Metric::RttMin => write!(f, "RTT Min"),
+            Metric::RttAvg => write!(f, "RTT Avg"),
+            Metric::RttMax => write!(f, "RTT Max"),
+            Metric::RttMdev => write!(f, "RTT Mdev"),
+            Metric::Reordered => write!(f, "Reordered"),
+            Metric::Duplicates => write!(f, "Duplicates"),
+        }
+    }
+}
+
+impl std::str::FromStr for Metric {
+    type Err = String;
+
+    /// Parse a metric name such as `avg_jitter`, `forward_p95_jitter`, `loss`, `rtt_avg` or
+    /// `reordered`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "loss" | "packet_loss" => Ok(Metric::PacketLoss),
+            "rtt_min" => Ok(Metric::RttMin),
+            "rtt_avg" => Ok(Metric::RttAvg),
+            "rtt_max" => Ok(Metric::RttMax),
+            "rtt_mdev" => Ok(Metric::RttMdev),
+            "reordered" => Ok(Metric::Reordered),
+            "duplicates" => Ok(Metric::Duplicates),
+            m => match m.strip_suffix("_jitter") {
+                Some(m) => {
+                    for c in Component::ALL {
+                        if let Some(aggr_method) = m.strip_prefix(&format!("{}_", c.name())) {
+                            return Ok(Metric::ComponentJitter(c, aggr_method.parse()?));
+                        }
+                    }
+                    Ok(Metric::Jitter(m.parse()?))
+                }
+                None => Err(format!("'{}' is not a valid metric", s)),
+            },
+        }
+    }
+}
+
+/// Thresholds for several metrics, each with its own warning and critical range.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MetricThresholds(Vec<(Metric, Thresholds)>);
+
+impl MetricThresholds {
+    /// Set the thresholds for a metric, replacing any thresholds previously set for it.
+    pub fn insert(&mut self, metric: Metric, thresholds: Thresholds) {
+        match self.0.iter_mut().find(|(m, _)| *m == metric) {
+            Some((_, t)) => *t = thresholds,
+            None => self.0.push((metric, thresholds)),
+        }
+    }
+
+    /// Get the thresholds for a metric, without any ranges if none were set.
+    pub fn get(&self, metric: &Metric) -> Thresholds {
+        self.0
+            .iter()
+            .find(|(m, _)| m == metric)
+            .map(|(_, t)| t.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(Metric, Thresholds)> {
+        self.0.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Parse a threshold definition such as `metric=avg_jitter,warn=5,crit=10`.
+///
+/// The `metric` key is required along with at least one of `warn` and `crit`, which take a
+/// range in monitoring plugin range syntax.
+pub fn parse_metric_threshold(s: &str) -> Result<(Metric, Thresholds), String> {
+    let mut metric = None;
+    let mut thresholds = Thresholds::default();
+
+    for pair in s.split(',') {
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("'{}' is not a key=value pair", pair))?;
+        let range = || {
+            ThresholdRange::from(value)
+                .map_err(|e| format!("unable to parse range '{}' with error: {}", value, e))
+        };
+        match key.trim() {
+            "metric" => metric = Some(value.parse::<Metric>()?),
+            "warn" | "warning" => thresholds.warning = Some(range()?),
+            "crit" | "critical" => thresholds.critical = Some(range()?),
+            k => return Err(format!("'{}' is not a valid threshold key", k)),
+        }
+    }
+
+    let metric = metric.ok_or_else(|| format!("'{}' does not name a metric", s))?;
+    if thresholds.warning.is_none() && thresholds.critical.is_none() {
+        return Err(format!("'{}' has neither a warn nor a crit range", s));
+    }
+
+    Ok((metric, thresholds))
+}
+
+#[cfg(test)]
+mod metric_thresholds_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_metric() {
+        assert_eq!(
+            "avg_jitter".parse(),
+            Ok(Metric::Jitter(AggregationMethod::Average))
+        );
+        assert_eq!(
+            "p99.9_jitter".parse(),
+            Ok(Metric::Jitter(AggregationMethod::Percentile(99.9)))
+        );
+        assert_eq!(
+            "reverse_max_jitter".parse(),
+            Ok(Metric::ComponentJitter(
+                Component::Reverse,
+                AggregationMethod::Max
+            ))
+        );
+        assert_eq!(
+            "ttfb_p95_jitter".parse(),
+            Ok(Metric::ComponentJitter(
+                Component::Ttfb,
+                AggregationMethod::Percentile(95.0)
+            ))
+        );
+        assert_eq!("loss".parse(), Ok(Metric::PacketLoss));
+        assert_eq!("rtt_mdev".parse(), Ok(Metric::RttMdev));
+        assert_eq!("Reordered".parse(), Ok(Metric::Reordered));
+        assert_eq!("duplicates".parse(), Ok(Metric::Duplicates));
+        assert!("jitter".parse::<Metric>().is_err());
+        assert!("bogus_jitter".parse::<Metric>().is_err());
+    }
+
+    #[test]
+    fn test_parse_with_both_ranges() {
+        let expected = (
+            Metric::Jitter(AggregationMethod::Average),
+            Thresholds {
+                warning: Some(ThresholdRange::from("5").unwrap()),
+                critical: Some(ThresholdRange::from("10").unwrap()),
+            },
+        );
+
+        assert_eq!(
+            parse_metric_threshold("metric=avg_jitter,warn=5,crit=10"),
+            Ok(expected)
+        );
+    }
+
+    #[test]
+    fn test_parse_with_only_critical() {
+        let expected = (
+            Metric::PacketLoss,
+            Thresholds {
+                warning: None,
+                critical: Some(ThresholdRange::from("@10:20").unwrap()),
+            },
+        );
+
+        assert_eq!(
+            parse_metric_threshold("crit=@10:20,metric=loss"),
+            Ok(expected)
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse_metric_threshold("warn=5,crit=10").is_err());
+        assert!(parse_metric_threshold("metric=avg_jitter").is_err());
+        assert!(parse_metric_threshold("metric=avg_jitter,warn=abc").is_err());
+        assert!(parse_metric_threshold("metric=avg_jitter,ok=5").is_err());
+        assert!(parse_metric_threshold("metric=avg_jitter,5").is_err());
+    }
+
+    #[test]
+    fn test_insert_replaces_and_get_defaults() {
+        let mut t = MetricThresholds::default();
+        let first = Thresholds {
+            warning: Some(ThresholdRange::from("5").unwrap()),
+            critical: None,
+        };
+        let second = Thresholds {
+            warning: None,
+            critical: Some(ThresholdRange::from("10").unwrap()),
+        };
+
+        t.insert(Metric::RttAvg, first);
+        t.insert(Metric::RttAvg, second.clone());
+
+        assert_eq!(t.get(&Metric::RttAvg), second);
+        assert_eq!(t.get(&Metric::RttMax), Thresholds::default());
+        assert_eq!(t.iter().count(), 1);
+    }
+}
+
+/// A measured value together with the thresholds it is evaluated against.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Perfdata {
+    pub metric: Metric,
+    pub value: f64,
+    pub thresholds: Thresholds,
+    /// The address the value was measured for, when every resolved address is probed.
+    pub address: Option<IpAddr>,
+    /// The DSCP marking of the probes the value was measured with, if any.
+    pub dscp: Option<Dscp>,
+}
+
+impl Perfdata {
+    fn label(&self) -> String {
+        let label = match self.address {
+            Some(address) => format!("{} {}", address, self.metric),
+            None => self.metric.to_string(),
+        };
+        match self.dscp {
+            Some(dscp) => format!("{} (DSCP {})", label, dscp),
+            None => label,
+        }
+    }
+}
+
+impl fmt::Display for Perfdata {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let min: f64 = 0.0;
+        let label = self.label();
+        let value = self.value;
+        let uom = self.metric.uom();
+        let w = self
+            .thresholds
+            .warning
+            .map(|w| w.to_string())
+            .unwrap_or_default();
+        let c = self
+            .thresholds
+            .critical
+            .map(|c| c.to_string())
+            .unwrap_or_default();
+        match self.metric.max() {
+            Some(max) => write!(f, "'{label}'={value}{uom};{w};{c};{min};{max}"),
+            None => write!(f, "'{label}'={value}{uom};{w};{c};{min}"),
+        }
+    }
+}
+
+/// Round trip time statistics in milliseconds, calculated from the pings that received a reply.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RttStatistics {
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+    /// The standard deviation of the round trip times.
+    pub mdev: f64,
+}
+
+/// The result of a jitter measurement.
+///
+/// Values that cannot be calculated because too few replies were received are `None`, e.g. the
+/// jitter of the deltas when no two consecutive pings received a reply.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Measurement {
+    /// The aggregated jitter in milliseconds for each requested aggregation method.
+    pub jitter: Vec<(AggregationMethod, Option<f64>)>,
+    /// The aggregated jitter in milliseconds of each part of the round trip measured separately
+    /// by the probe type, for each requested aggregation method.
+    pub component_jitter: Vec<(Component, AggregationMethod, Option<f64>)>,
+    /// The percentage of pings that did not receive a reply.
+    pub packet_loss: f64,
+    /// The round trip time statistics, or `None` if every ping was lost.
+    pub rtt: Option<RttStatistics>,
+    /// The replies that arrived out of order or more than once, for probe types that number
+    /// their probes.
+    pub sequence_errors: Option<SequenceErrors>,
+}
+
+impl Measurement {
+    /// All measured metrics in the order they are reported, with `None` for the values that
+    /// could not be calculated.
+    pub fn metrics(&self) -> Vec<(Metric, Option<f64>)> {
+        let mut metrics: Vec<(Metric, Option<f64>)> = self
+            .jitter
+            .iter()
+            .map(|(m, j)| (Metric::Jitter(*m), *j))
+            .collect();
+
+        metrics.extend(
+            self.component_jitter
+                .iter()
+                .map(|(c, m, j)| (Metric::ComponentJitter(*c, *m), *j)),
+        );
+
+        let rtt = self.rtt.as_ref();
+        metrics.extend([
+            (Metric::PacketLoss, Some(self.packet_loss)),
+            (Metric::RttMin, rtt.map(|r| r.min)),
+            (Metric::RttAvg, rtt.map(|r| r.avg)),
+            (Metric::RttMax, rtt.map(|r| r.max)),
+            (Metric::RttMdev, rtt.map(|r| r.mdev)),
+        ]);
+
+        if let Some(errors) = self.sequence_errors {
+            metrics.extend([
+                (Metric::Reordered, Some(errors.reordered.into())),
+                (Metric::Duplicates, Some(errors.duplicates.into())),
+            ]);
+        }
+
+        metrics
+    }
+}
+
+#[cfg(test)]
+mod measurement_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_metrics_without_replies() {
+        let measurement = Measurement {
+            jitter: vec![(AggregationMethod::Average, None)],
+            component_jitter: Vec::new(),
+            packet_loss: 100.0,
+            rtt: None,
+            sequence_errors: None,
+        };
+
+        let expected = vec![
+            (Metric::Jitter(AggregationMethod::Average), None),
+            (Metric::PacketLoss, Some(100.0)),
+            (Metric::RttMin, None),
+            (Metric::RttAvg, None),
+            (Metric::RttMax, None),
+            (Metric::RttMdev, None),
+        ];
+        assert_eq!(measurement.metrics(), expected);
+    }
+
+    #[test]
+    fn test_metrics_with_sequence_errors() {
+        let measurement = Measurement {
+            jitter: vec![(AggregationMethod::Average, Some(0.5))],
+            component_jitter: Vec::new(),
+            packet_loss: 10.0,
+            rtt: Some(RttStatistics {
+                min: 1.0,
+                avg: 2.0,
+                max: 3.0,
+                mdev: 0.5,
+            }),
+            sequence_errors: Some(SequenceErrors {
+                reordered: 2,
+                duplicates: 1,
+            }),
+        };
+        let metrics = measurement.metrics();
+
+        assert_eq!(
+            metrics[metrics.len() - 2..],
+            [
+                (Metric::Reordered, Some(2.0)),
+                (Metric::Duplicates, Some(1.0))
+            ]
+        );
+    }
+}
+
 #[non_exhaustive]
 #[derive(Debug, PartialEq)]
 pub enum UnknownVariant {
@@ -136,29 +786,37 @@ pub enum UnknownVariant {
     InvalidAddr(String),
     InvalidMinMaxInterval(u64, u64),
     ClapError(String),
+    MaxLossExceeded(f64, f64),
+    MismatchedThresholds(String, usize, usize),
     NoThresholds,
+    /// An address of several could not be measured, with the values of those that were.
+    PartialFailure(Box<UnknownVariant>, Vec<Perfdata>),
     RangeParseError(String, RangeError),
     Timeout(Duration),
+    UnmeasuredMetric(Metric),
 }
 
 #[derive(Debug, PartialEq)]
-pub enum Status<'a> {
-    Ok(AggregationMethod, f64, &'a Thresholds),
-    Warning(AggregationMethod, f64, &'a Thresholds),
-    Critical(AggregationMethod, f64, &'a Thresholds),
+pub enum Status {
+    Ok(Vec<Perfdata>),
+    Warning(Vec<Perfdata>),
+    Critical(Vec<Perfdata>),
     Unknown(UnknownVariant),
 }
 
-fn display_string(label: &str, status: &str, uom: &str, f: f64, t: &Thresholds) -> String {
-    let min: f64 = 0.0;
-    match (t.warning, t.critical) {
-        (Some(w), Some(c)) => {
-            format!("{status} - {label}: {f}{uom}|'{label}'={f}{uom};{w};{c};{min}")
-        }
-        (Some(w), None) => format!("{status} - {label}: {f}{uom}|'{label}'={f}{uom};{w};;{min}"),
-        (None, Some(c)) => format!("{status} - {label}: {f}{uom}|'{label}'={f}{uom};;{c};{min}"),
-        (None, None) => format!("{status} - {label}: {f}{uom}|'{label}'={f}{uom};;;{min}"),
-    }
+fn display_string(status: &str, perfdata: &[Perfdata]) -> String {
+    let summary = perfdata
+        .iter()
+        .map(|p| format!("{}: {}{}", p.label(), p.value, p.metric.uom()))
+        .collect::<Vec<String>>()
+        .join(", ");
+    let perfdata = perfdata
+        .iter()
+        .map(|p| p.to_string())
+        .collect::<Vec<String>>()
+        .join(" ");
+
+    format!("{status} - {summary}|{perfdata}")
 }
 
 #[cfg(test)]
@@ -166,6 +824,16 @@ mod display_string_tests {
     use super::*;
     use pretty_assertions::assert_eq;
 
+    fn jitter(thresholds: Thresholds) -> Perfdata {
+        Perfdata {
+            metric: Metric::Jitter(AggregationMethod::Average),
+            value: 0.1,
+            thresholds,
+            address: None,
+            dscp: None,
+        }
+    }
+
     #[test]
     fn test_with_both_thresholds() {
         let thresholds = Thresholds {
@@ -174,7 +842,7 @@ mod display_string_tests {
         };
 
         let expected = "OK - Average Jitter: 0.1ms|'Average Jitter'=0.1ms;0:0.5;0:1;0";
-        let actual = display_string("Average Jitter", "OK", "ms", 0.1, &thresholds);
+        let actual = display_string("OK", &[jitter(thresholds)]);
 
         assert_eq!(actual, expected);
     }
@@ -187,7 +855,7 @@ mod display_string_tests {
         };
 
         let expected = "OK - Average Jitter: 0.1ms|'Average Jitter'=0.1ms;0:0.5;;0";
-        let actual = display_string("Average Jitter", "OK", "ms", 0.1, &thresholds);
+        let actual = display_string("OK", &[jitter(thresholds)]);
 
         assert_eq!(actual, expected);
     }
@@ -200,7 +868,7 @@ mod display_string_tests {
         };
 
         let expected = "OK - Average Jitter: 0.1ms|'Average Jitter'=0.1ms;;0:0.5;0";
-        let actual = display_string("Average Jitter", "OK", "ms", 0.1, &thresholds);
+        let actual = display_string("OK", &[jitter(thresholds)]);
 
         assert_eq!(actual, expected);
     }
@@ -213,86 +881,143 @@ mod display_string_tests {
         };
 
         let expected = "OK - Average Jitter: 0.1ms|'Average Jitter'=0.1ms;;;0";
-        let actual = display_string("Average Jitter", "OK", "ms", 0.1, &thresholds);
+        let actual = display_string("OK", &[jitter(thresholds)]);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_with_packet_loss() {
+        let loss = Perfdata {
+            metric: Metric::PacketLoss,
+            value: 20.0,
+            thresholds: Thresholds::default(),
+            address: None,
+            dscp: None,
+        };
+
+        let expected = "OK - Average Jitter: 0.1ms, Packet Loss: 20%|\
+                        'Average Jitter'=0.1ms;;;0 'Packet Loss'=20%;;;0;100";
+        let actual = display_string("OK", &[jitter(Thresholds::default()), loss]);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_with_dscp() {
+        let jitter = Perfdata {
+            address: Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+            dscp: Some("EF".parse().unwrap()),
+            ..jitter(Thresholds::default())
+        };
+
+        let expected = "OK - 127.0.0.1 Average Jitter (DSCP EF): 0.1ms|\
+                        '127.0.0.1 Average Jitter (DSCP EF)'=0.1ms;;;0";
+        let actual = display_string("OK", &[jitter]);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_with_count() {
+        let reordered = Perfdata {
+            metric: Metric::Reordered,
+            value: 2.0,
+            ..jitter(Thresholds::default())
+        };
+
+        let expected = "OK - Reordered: 2|'Reordered'=2;;;0";
+        let actual = display_string("OK", &[reordered]);
 
         assert_eq!(actual, expected);
     }
 }
 
-impl fmt::Display for Status<'_> {
+impl fmt::Display for Status {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let uom = "ms";
-        let label = match self {
-            Status::Ok(AggregationMethod::Average, _, _) => "Average Jitter",
-            Status::Ok(AggregationMethod::Median, _, _) => "Median Jitter",
-            Status::Ok(AggregationMethod::Max, _, _) => "Max Jitter",
-            Status::Ok(AggregationMethod::Min, _, _) => "Min Jitter",
-            Status::Warning(AggregationMethod::Average, _, _) => "Average Jitter",
-            Status::Warning(AggregationMethod::Median, _, _) => "Median Jitter",
-            Status::Warning(AggregationMethod::Max, _, _) => "Max Jitter",
-            Status::Warning(AggregationMethod::Min, _, _) => "Min Jitter",
-            Status::Critical(AggregationMethod::Average, _, _) => "Average Jitter",
-            Status::Critical(AggregationMethod::Median, _, _) => "Median Jitter",
-            Status::Critical(AggregationMethod::Max, _, _) => "Max Jitter",
-            Status::Critical(AggregationMethod::Min, _, _) => "Min Jitter",
-            Status::Unknown(_) => "Unknown",
-        };
+        match self {
+            Status::Ok(p) => {
+                write!(f, "{}", display_string("OK", p))
+            }
+            Status::Warning(p) => {
+                write!(f, "{}", display_string("WARNING", p))
+            }
+            Status::Critical(p) => {
+                write!(f, "{}", display_string("CRITICAL", p))
+            }
+            Status::Unknown(u) => {
+                write!(f, "UNKNOWN - {}", u)
+            }
+        }
+    }
+}
 
+impl fmt::Display for UnknownVariant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Status::Ok(_, n, t) => {
-                write!(f, "{}", display_string(label, "OK", uom, *n, t))
+            UnknownVariant::Error(e) => {
+                write!(f, "An error occurred: '{}'", e)
             }
-            Status::Warning(_, n, t) => {
-                write!(f, "{}", display_string(label, "WARNING", uom, *n, t))
+            UnknownVariant::FailedToInitLogger(s) => {
+                write!(f, "Failed to initialize logger with error: '{}'", s)
             }
-            Status::Critical(_, n, t) => {
-                write!(f, "{}", display_string(label, "CRITICAL", uom, *n, t))
+            UnknownVariant::InvalidAddr(s) => {
+                write!(f, "Invalid address or hostname: {}", s)
             }
-            Status::Unknown(UnknownVariant::Error(e)) => {
-                write!(f, "UNKNOWN - An error occurred: '{}'", e)
+            UnknownVariant::InvalidMinMaxInterval(min, max) => {
+                write!(f, "Invalid min/max interval: min: {}, max: {}", min, max)
             }
-            Status::Unknown(UnknownVariant::FailedToInitLogger(s)) => {
+            UnknownVariant::ClapError(s) => {
+                let trimmed = s.trim_end();
+                let without_leading_error = trimmed.trim_start_matches("error: ");
                 write!(
                     f,
-                    "UNKNOWN - Failed to initialize logger with error: '{}'",
-                    s
+                    "Command line parsing produced an error: {}",
+                    without_leading_error,
                 )
             }
-            Status::Unknown(UnknownVariant::InvalidAddr(s)) => {
-                write!(f, "UNKNOWN - Invalid address or hostname: {}", s)
-            }
-            Status::Unknown(UnknownVariant::InvalidMinMaxInterval(min, max)) => {
+            UnknownVariant::MaxLossExceeded(loss, max) => {
                 write!(
                     f,
-                    "UNKNOWN - Invalid min/max interval: min: {}, max: {}",
-                    min, max
+                    "Packet loss of {}% exceeds the maximum allowed loss of {}%",
+                    loss, max
                 )
             }
-            Status::Unknown(UnknownVariant::ClapError(s)) => {
-                let trimmed = s.trim_end();
-                let without_leading_error = trimmed.trim_start_matches("error: ");
+            UnknownVariant::MismatchedThresholds(name, n, count) => {
                 write!(
                     f,
-                    "UNKNOWN - Command line parsing produced an error: {}",
-                    without_leading_error,
+                    "Got {} {} thresholds for {} aggregation methods. Provide one per method or a single threshold for all methods.",
+                    n, name, count
                 )
             }
-            Status::Unknown(UnknownVariant::NoThresholds) => {
+            UnknownVariant::NoThresholds => {
+                write!(f, "No thresholds provided. Provide at least one threshold.")
+            }
+            UnknownVariant::PartialFailure(u, perfdata) => {
+                let perfdata = perfdata
+                    .iter()
+                    .map(|p| p.to_string())
+                    .collect::<Vec<String>>()
+                    .join(" ");
+                write!(f, "{}|{}", u, perfdata)
+            }
+            UnknownVariant::RangeParseError(s, e) => {
+                write!(f, "Unable to parse range '{}' with error: {}", s, e)
+            }
+            UnknownVariant::Timeout(d) => {
                 write!(
                     f,
-                    "UNKNOWN - No thresholds provided. Provide at least one threshold."
+                    "The deadline of {:?} passed before enough samples were collected",
+                    d
                 )
             }
-            Status::Unknown(UnknownVariant::RangeParseError(s, e)) => {
+            UnknownVariant::UnmeasuredMetric(m) => {
                 write!(
                     f,
-                    "UNKNOWN - Unable to parse range '{}' with error: {}",
-                    s, e
+                    "A threshold was provided for '{}', which is not measured",
+                    m
                 )
             }
-            Status::Unknown(UnknownVariant::Timeout(d)) => {
-                write!(f, "UNKNOWN - Ping timeout occurred after {:?}", d)
-            }
         }
     }
 }
@@ -302,13 +1027,23 @@ mod status_display_tests {
     use super::*;
     use pretty_assertions::assert_eq;
 
+    fn jitter(aggr_method: AggregationMethod, thresholds: Thresholds) -> Vec<Perfdata> {
+        vec![Perfdata {
+            metric: Metric::Jitter(aggr_method),
+            value: 0.1,
+            thresholds,
+            address: None,
+            dscp: None,
+        }]
+    }
+
     #[test]
     fn test_with_ok() {
         let t = Thresholds {
             warning: Some(ThresholdRange::from("0:0.5").unwrap()),
             critical: Some(ThresholdRange::from("0:1").unwrap()),
         };
-        let status = Status::Ok(AggregationMethod::Average, 0.1, &t);
+        let status = Status::Ok(jitter(AggregationMethod::Average, t));
         let expected = "OK - Average Jitter: 0.1ms|'Average Jitter'=0.1ms;0:0.5;0:1;0";
         let actual = format!("{}", status);
 
@@ -323,7 +1058,7 @@ mod status_display_tests {
             warning: Some(ThresholdRange::from("0.5").unwrap()),
             critical: Some(ThresholdRange::from("1").unwrap()),
         };
-        let status = Status::Ok(AggregationMethod::Median, 0.1, &t);
+        let status = Status::Ok(jitter(AggregationMethod::Median, t));
         let expected = "OK - Median Jitter: 0.1ms|'Median Jitter'=0.1ms;0:0.5;0:1;0";
         let actual = format!("{}", status);
 
@@ -336,7 +1071,7 @@ mod status_display_tests {
             warning: Some(ThresholdRange::from("0:0.5").unwrap()),
             critical: Some(ThresholdRange::from("0:1").unwrap()),
         };
-        let status = Status::Warning(AggregationMethod::Average, 0.1, &t);
+        let status = Status::Warning(jitter(AggregationMethod::Average, t));
         let expected = "WARNING - Average Jitter: 0.1ms|'Average Jitter'=0.1ms;0:0.5;0:1;0";
         let actual = format!("{}", status);
 
@@ -349,7 +1084,7 @@ mod status_display_tests {
             warning: Some(ThresholdRange::from("0:0.5").unwrap()),
             critical: Some(ThresholdRange::from("0:1").unwrap()),
         };
-        let status = Status::Critical(AggregationMethod::Max, 0.1, &t);
+        let status = Status::Critical(jitter(AggregationMethod::Max, t));
         let expected = "CRITICAL - Max Jitter: 0.1ms|'Max Jitter'=0.1ms;0:0.5;0:1;0";
         let actual = format!("{}", status);
 
@@ -367,22 +1102,85 @@ mod status_display_tests {
 
         assert_eq!(actual, expected);
     }
-}
-
-impl Status<'_> {
-    pub fn to_int(&self) -> i32 {
-        match self {
-            Status::Ok(_, _, _) => 0,
-            Status::Warning(_, _, _) => 1,
-            Status::Critical(_, _, _) => 2,
-            Status::Unknown(_) => 3,
-        }
-    }
-}
 
-fn abs_diff_duration(a: Duration, b: Duration) -> Duration {
-    if a > b {
-        a - b
+    #[test]
+    fn test_with_multiple_methods() {
+        let t = Thresholds {
+            warning: Some(ThresholdRange::from("0:0.5").unwrap()),
+            critical: None,
+        };
+        let mut perfdata = jitter(AggregationMethod::Average, t.clone());
+        perfdata.extend(jitter(AggregationMethod::Max, t));
+        let status = Status::Ok(perfdata);
+        let expected = "OK - Average Jitter: 0.1ms, Max Jitter: 0.1ms|\
+                        'Average Jitter'=0.1ms;0:0.5;;0 'Max Jitter'=0.1ms;0:0.5;;0";
+        let actual = format!("{}", status);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_with_mismatched_thresholds() {
+        let status = Status::Unknown(UnknownVariant::MismatchedThresholds(
+            "warning".to_string(),
+            3,
+            2,
+        ));
+
+        let expected = "UNKNOWN - Got 3 warning thresholds for 2 aggregation methods. \
+                        Provide one per method or a single threshold for all methods.";
+        let actual = format!("{}", status);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_with_unmeasured_metric() {
+        let status = Status::Unknown(UnknownVariant::UnmeasuredMetric(Metric::Jitter(
+            AggregationMethod::Percentile(95.0),
+        )));
+
+        let expected = "UNKNOWN - A threshold was provided for 'P95 Jitter', which is not measured";
+        let actual = format!("{}", status);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_with_max_loss_exceeded() {
+        let status = Status::Unknown(UnknownVariant::MaxLossExceeded(30.0, 10.0));
+
+        let expected = "UNKNOWN - Packet loss of 30% exceeds the maximum allowed loss of 10%";
+        let actual = format!("{}", status);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_with_timeout() {
+        let status = Status::Unknown(UnknownVariant::Timeout(Duration::from_secs(10)));
+
+        let expected = "UNKNOWN - The deadline of 10s passed before enough samples were collected";
+        let actual = format!("{}", status);
+
+        assert_eq!(actual, expected);
+    }
+}
+
+impl Status {
+    pub fn to_int(&self) -> i32 {
+        match self {
+            Status::Ok(_) => 0,
+            Status::Warning(_) => 1,
+            Status::Critical(_) => 2,
+            Status::Unknown(_) => 3,
+        }
+    }
+}
+
+fn abs_diff_duration(a: Duration, b: Duration) -> Duration {
+    if a > b {
+        a - b
     } else {
         b - a
     }
@@ -607,8 +1405,105 @@ where
     resolver(addr)
 }
 
-fn parse_addr(addr: &str) -> Result<Vec<IpAddr>, CheckJitterError> {
-    parse_addr_with_resolver(addr, default_resolver)
+/// The IP version of the addresses to probe.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum AddressFamily {
+    /// Use addresses of both versions, in the order they were resolved.
+    #[default]
+    Any,
+    Ipv4,
+    Ipv6,
+}
+
+impl AddressFamily {
+    fn contains(&self, ip: &IpAddr) -> bool {
+        match self {
+            AddressFamily::Any => true,
+            AddressFamily::Ipv4 => ip.is_ipv4(),
+            AddressFamily::Ipv6 => ip.is_ipv6(),
+        }
+    }
+}
+
+impl fmt::Display for AddressFamily {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AddressFamily::Any => write!(f, "IPv4 or IPv6"),
+            AddressFamily::Ipv4 => write!(f, "IPv4"),
+            AddressFamily::Ipv6 => write!(f, "IPv6"),
+        }
+    }
+}
+
+fn filter_family(
+    addr: &str,
+    ips: Vec<IpAddr>,
+    family: AddressFamily,
+) -> Result<Vec<IpAddr>, CheckJitterError> {
+    let ips: Vec<IpAddr> = ips.into_iter().filter(|ip| family.contains(ip)).collect();
+    if ips.is_empty() {
+        return Err(CheckJitterError::NoAddressOfFamily(
+            addr.to_string(),
+            family,
+        ));
+    }
+    Ok(ips)
+}
+
+fn parse_addr(addr: &str, family: AddressFamily) -> Result<Vec<IpAddr>, CheckJitterError> {
+    filter_family(
+        addr,
+        parse_addr_with_resolver(addr, default_resolver)?,
+        family,
+    )
+}
+
+#[cfg(test)]
+mod filter_family_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn dual_stack() -> Vec<IpAddr> {
+        vec![
+            IpAddr::V6(Ipv6Addr::LOCALHOST),
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+        ]
+    }
+
+    #[test]
+    fn test_any() {
+        let result = filter_family("localhost", dual_stack(), AddressFamily::Any);
+        assert_eq!(result, Ok(dual_stack()));
+    }
+
+    #[test]
+    fn test_ipv4() {
+        let result = filter_family("localhost", dual_stack(), AddressFamily::Ipv4);
+        assert_eq!(result, Ok(vec![IpAddr::V4(Ipv4Addr::LOCALHOST)]));
+    }
+
+    #[test]
+    fn test_ipv6() {
+        let result = filter_family("localhost", dual_stack(), AddressFamily::Ipv6);
+        assert_eq!(result, Ok(vec![IpAddr::V6(Ipv6Addr::LOCALHOST)]));
+    }
+
+    #[test]
+    fn test_missing_family() {
+        let ips = vec![IpAddr::V4(Ipv4Addr::LOCALHOST)];
+        let result = filter_family("localhost", ips, AddressFamily::Ipv6);
+        assert_eq!(
+            result,
+            Err(CheckJitterError::NoAddressOfFamily(
+                "localhost".to_string(),
+                AddressFamily::Ipv6
+            ))
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "No IPv6 address found for: localhost"
+        );
+    }
 }
 
 #[cfg(test)]
@@ -728,23 +1623,92 @@ mod parse_addr_tests {
     }
 }
 
-fn run_samples(
+/// The reply to a single probe.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reply {
+    pub rtt: Duration,
+    /// Parts of the round trip measured separately, in nanoseconds. These may include a constant
+    /// offset, such as the difference between two unsynchronized clocks, so only their variation
+    /// is meaningful.
+    pub components: Vec<(Component, i64)>,
+}
+
+impl From<Duration> for Reply {
+    fn from(rtt: Duration) -> Self {
+        Reply {
+            rtt,
+            components: Vec::new(),
+        }
+    }
+}
+
+/// The replies to the probes sent to a single address.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Samples {
+    /// The reply to each probe in the order the probes were sent, or `None` if it was lost.
+    replies: Vec<Option<Reply>>,
+    sequence_errors: Option<SequenceErrors>,
+}
+
+/// Create the pinger for the probe type, connected to `ip`.
+fn new_pinger(
     ip: IpAddr,
     socket_type: SocketType,
+    options: &SocketOptions,
+) -> Result<Box<dyn Pinger>, CheckJitterError> {
+    Ok(match socket_type {
+        SocketType::Datagram => Box::new(IcmpEcho::datagram(ip, options)?),
+        SocketType::Raw => Box::new(IcmpEcho::raw(ip, options)?),
+        SocketType::Tcp(port) => Box::new(tcp::TcpConnect::new(SocketAddr::new(ip, port), options)),
+        SocketType::Udp(port) => {
+            Box::new(udp::UdpEcho::connect(SocketAddr::new(ip, port), options)?)
+        }
+        SocketType::Twamp(port) => Box::new(twamp::SessionSender::connect(
+            SocketAddr::new(ip, port),
+            options,
+        )?),
+        SocketType::Timestamp => Box::new(timestamp::TimestampSession::connect(ip, options)?),
+        // The host of the URL is resolved again by every request, to time the lookup.
+        SocketType::Http(url) => Box::new(http::HttpProbe::new(&url, ip, options)?),
+        SocketType::Dns {
+            port,
+            name,
+            record_type,
+        } => Box::new(dns::DnsQuery::connect(
+            SocketAddr::new(ip, port),
+            &name,
+            record_type,
+            options,
+        )?),
+    })
+}
+
+/// Send `samples` probes with `pinger`, waiting for the given intervals between them.
+///
+/// Each interval is counted from the end of the previous probe. The first error returned by the
+/// pinger aborts the measurement.
+///
+/// If a `deadline` is given, no probe is sent or waited for beyond it, and only the replies of
+/// the probes completed before it are returned. A probe that is still waiting for its reply when
+/// the deadline passes is not counted as lost, but left out. A probe that times out before the
+/// deadline is lost as usual.
+pub(crate) fn run_samples(
+    pinger: &mut dyn Pinger,
     samples: u8,
     timeout: Duration,
     intervals: Vec<Duration>,
-) -> Result<Vec<Duration>, CheckJitterError> {
-    let ping_function = match socket_type {
-        SocketType::Datagram => ping::dgramsock::ping,
-        SocketType::Raw => ping::rawsock::ping,
-    };
-
-    let mut durations = Vec::<Duration>::with_capacity(samples as usize);
+    deadline: Option<Instant>,
+) -> Result<Vec<Option<Reply>>, CheckJitterError> {
+    let mut replies = Vec::<Option<Reply>>::with_capacity(samples as usize);
     let mut intervals_iter = intervals.into_iter();
     let mut next_ping_time = Instant::now();
 
     for i in 0..samples {
+        if deadline.is_some_and(|d| next_ping_time >= d) {
+            info!("Deadline reached after {} of {} samples", i, samples);
+            break;
+        }
+
         let now = Instant::now();
 
         if now < next_ping_time {
@@ -752,66 +1716,365 @@ fn run_samples(
             std::thread::sleep(sleep_duration);
         }
 
-        let start_time = Instant::now();
+        let probe_timeout = match deadline {
+            Some(d) => timeout.min(d.saturating_duration_since(Instant::now())),
+            None => timeout,
+        };
+        let reply = pinger.probe(probe_timeout)?;
 
-        match ping_function(ip, Some(timeout), None, None, None, None) {
-            Ok(_) => {
-                let end_time = Instant::now();
-                let duration = end_time - start_time;
-                durations.push(duration);
-                debug!("Ping round {}, duration: {:?}", i + 1, duration);
-
-                if let Some(interval) = intervals_iter.next() {
-                    next_ping_time = end_time + interval;
-                } else {
-                    next_ping_time = end_time;
-                }
-            }
-            Err(e) => {
-                if let ping::Error::IoError { error } = &e {
-                    match error.kind() {
-                        std::io::ErrorKind::PermissionDenied => {
-                            return Err(CheckJitterError::PermissionDenied);
-                        }
-                        std::io::ErrorKind::WouldBlock => {
-                            return Err(CheckJitterError::Timeout(timeout.as_millis().to_string()));
-                        }
-                        _ => {
-                            return Err(CheckJitterError::PingIoError(error.to_string()));
-                        }
-                    }
-                }
-                return Err(CheckJitterError::PingError(PingErrorWrapper(e)));
+        match &reply {
+            Some(r) => debug!("Ping round {}, duration: {:?}", i + 1, r.rtt),
+            None if deadline.is_some_and(|d| Instant::now() >= d) => {
+                info!("Deadline reached after {} of {} samples", i, samples);
+                break;
             }
-        };
+            None => debug!("Ping round {}, timed out after {:?}", i + 1, timeout),
+        }
+        replies.push(reply);
+
+        let end_time = Instant::now();
+        if let Some(interval) = intervals_iter.next() {
+            next_ping_time = end_time + interval;
+        } else {
+            next_ping_time = end_time;
+        }
+    }
+
+    debug!("Ping replies: {:?}", replies);
+    Ok(replies)
+}
+
+#[cfg(test)]
+mod run_samples_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::net::TcpListener;
+
+    fn rtts(replies: &[Option<Reply>]) -> Vec<Option<Duration>> {
+        replies.iter().map(|r| r.as_ref().map(|r| r.rtt)).collect()
+    }
+
+    #[test]
+    fn test_with_scripted_replies() {
+        let mut pinger = MockPinger::from_rtts(&[Some(10), None, Some(12), Some(11)]);
+
+        let replies =
+            run_samples(&mut pinger, 4, Duration::from_secs(1), Vec::new(), None).unwrap();
+
+        let expected = vec![
+            Some(Duration::from_millis(10)),
+            None,
+            Some(Duration::from_millis(12)),
+            Some(Duration::from_millis(11)),
+        ];
+        assert_eq!(rtts(&replies), expected);
+        assert_eq!(pinger.probes(), 4);
+    }
+
+    #[test]
+    fn test_with_error() {
+        let mut pinger = MockPinger::new(vec![
+            Ok(Some(Reply::from(Duration::from_millis(10)))),
+            Err(CheckJitterError::PermissionDenied),
+            Ok(Some(Reply::from(Duration::from_millis(10)))),
+        ]);
+
+        let result = run_samples(&mut pinger, 3, Duration::from_secs(1), Vec::new(), None);
+
+        assert_eq!(result, Err(CheckJitterError::PermissionDenied));
+        assert_eq!(pinger.probes(), 2);
+    }
+
+    #[test]
+    fn test_waits_for_intervals() {
+        let mut pinger = MockPinger::from_rtts(&[Some(1), Some(1), Some(1)]);
+        let intervals = vec![Duration::from_millis(20), Duration::from_millis(30)];
+        let start_time = Instant::now();
+
+        let replies = run_samples(&mut pinger, 3, Duration::from_secs(1), intervals, None).unwrap();
+
+        assert_eq!(replies.len(), 3);
+        assert!(start_time.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_stops_at_deadline() {
+        let mut pinger = MockPinger::from_rtts(&[Some(1), Some(1), Some(1)]);
+        let intervals = vec![Duration::from_millis(20), Duration::from_millis(500)];
+        let deadline = Instant::now() + Duration::from_millis(100);
+
+        let replies = run_samples(
+            &mut pinger,
+            3,
+            Duration::from_secs(1),
+            intervals,
+            Some(deadline),
+        )
+        .unwrap();
+
+        assert_eq!(replies.len(), 2);
+        assert_eq!(pinger.probes(), 2);
+        assert!(Instant::now() < deadline);
+    }
+
+    #[test]
+    fn test_with_loss_before_deadline() {
+        let mut pinger = MockPinger::from_rtts(&[Some(10), None, Some(12)]);
+        // The deadline is earlier than the timeout, so it limits the wait for every reply.
+        let deadline = Instant::now() + Duration::from_secs(30);
+
+        let replies = run_samples(
+            &mut pinger,
+            3,
+            Duration::from_secs(60),
+            Vec::new(),
+            Some(deadline),
+        )
+        .unwrap();
+
+        let expected = vec![
+            Some(Duration::from_millis(10)),
+            None,
+            Some(Duration::from_millis(12)),
+        ];
+        assert_eq!(rtts(&replies), expected);
+    }
+
+    #[test]
+    fn test_with_passed_deadline() {
+        let mut pinger = MockPinger::from_rtts(&[Some(1), Some(1)]);
+        let deadline = Some(Instant::now());
+
+        let replies = run_samples(&mut pinger, 2, Duration::from_secs(1), Vec::new(), deadline);
+
+        assert_eq!(replies, Ok(Vec::new()));
+        assert_eq!(pinger.probes(), 0);
     }
 
-    debug!("Ping durations: {:?}", durations);
-    Ok(durations)
+    #[test]
+    fn test_with_tcp_listener() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let mut pinger = new_pinger(ip, SocketType::Tcp(port), &SocketOptions::default()).unwrap();
+
+        let replies =
+            run_samples(pinger.as_mut(), 5, Duration::from_secs(1), Vec::new(), None).unwrap();
+
+        assert_eq!(replies.len(), 5);
+        assert!(replies.iter().all(|r| r.is_some()));
+    }
+
+    #[test]
+    fn test_with_closed_tcp_port() {
+        // Bind and drop a listener to get a port that is very likely closed.
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let mut pinger = new_pinger(ip, SocketType::Tcp(port), &SocketOptions::default()).unwrap();
+
+        let result = run_samples(pinger.as_mut(), 3, Duration::from_secs(1), Vec::new(), None);
+
+        assert!(matches!(result, Err(CheckJitterError::PingIoError(_))));
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn get_durations(
     addr: &str,
+    family: AddressFamily,
     socket_type: SocketType,
+    options: &SocketOptions,
     samples: u8,
     timeout: Duration,
     min_interval: u64,
     max_interval: u64,
-) -> Result<Vec<Duration>, CheckJitterError> {
+) -> Result<Samples, CheckJitterError> {
     // NOTE: Only the first IP address from the list of resolved addresses will be used.
-    // TODO: This may change in the future if we decide to ping all resolved addresses by default
-    //       or provide an option to do so.
-    let ip = match parse_addr(addr)?.first() {
+    //       Use `get_jitter_per_address` to probe all of them.
+    let ip = match parse_addr(addr, family)?.first() {
         Some(ip) => *ip,
         None => return Err(CheckJitterError::DnsLookupFailed(addr.to_string())),
     };
 
+    get_durations_for_ip(
+        ip,
+        socket_type,
+        options,
+        samples,
+        timeout,
+        min_interval,
+        max_interval,
+    )
+}
+
+fn get_durations_for_ip(
+    ip: IpAddr,
+    socket_type: SocketType,
+    options: &SocketOptions,
+    samples: u8,
+    timeout: Duration,
+    min_interval: u64,
+    max_interval: u64,
+) -> Result<Samples, CheckJitterError> {
     if samples < 2 {
         return Err(CheckJitterError::InsufficientSamples(samples));
     }
 
-    let intervals = generate_intervals(samples - 1, min_interval, max_interval);
-    run_samples(ip, socket_type, samples, timeout, intervals)
+    let mut pinger = new_pinger(ip, socket_type, options)?;
+    let replies = match options.fixed_rate {
+        Some(interval) => {
+            let replies = pinger.probe_at_rate(samples, interval, timeout, options.deadline)?;
+            debug!("Ping replies: {:?}", replies);
+            replies
+        }
+        None => {
+            let intervals = generate_intervals(samples - 1, min_interval, max_interval);
+            run_samples(
+                pinger.as_mut(),
+                samples,
+                timeout,
+                intervals,
+                options.deadline,
+            )?
+        }
+    };
+    if replies.len() < 2 {
+        return Err(CheckJitterError::DeadlineExceeded(replies.len()));
+    }
+
+    let sequence_errors = pinger.sequence_errors();
+    if let Some(errors) = &sequence_errors {
+        debug!("Sequence errors: {:?}", errors);
+    }
+
+    Ok(Samples {
+        replies,
+        sequence_errors,
+    })
+}
+
+/// Get the durations of a component of the replies, shifted so that the smallest is zero.
+///
+/// The shift removes any constant offset from the component, such as the difference between two
+/// unsynchronized clocks, which does not affect the variation of the durations.
+fn component_durations(replies: &[Option<Reply>], component: Component) -> Vec<Option<Duration>> {
+    let values: Vec<Option<i64>> = replies
+        .iter()
+        .map(|r| {
+            r.as_ref().and_then(|r| {
+                r.components
+                    .iter()
+                    .find(|(c, _)| *c == component)
+                    .map(|(_, v)| *v)
+            })
+        })
+        .collect();
+
+    let min = values.iter().flatten().min().copied().unwrap_or_default();
+    let durations = values
+        .iter()
+        .map(|v| v.map(|v| Duration::from_nanos(v.abs_diff(min))))
+        .collect();
+    debug!("{} durations: {:?}", component, durations);
+
+    durations
+}
+
+#[cfg(test)]
+mod component_durations_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn reply(forward: i64) -> Option<Reply> {
+        Some(Reply {
+            rtt: Duration::from_millis(1),
+            components: vec![(Component::Forward, forward)],
+        })
+    }
+
+    #[test]
+    fn test_with_negative_offset() {
+        let replies = vec![reply(-5_000), None, reply(-7_000), reply(-4_000)];
+
+        let expected = vec![
+            Some(Duration::from_nanos(2_000)),
+            None,
+            Some(Duration::from_nanos(0)),
+            Some(Duration::from_nanos(3_000)),
+        ];
+
+        assert_eq!(component_durations(&replies, Component::Forward), expected);
+    }
+
+    #[test]
+    fn test_with_missing_component() {
+        let replies = vec![reply(1_000), reply(2_000)];
+
+        let expected: Vec<Option<Duration>> = vec![None, None];
+
+        assert_eq!(component_durations(&replies, Component::Reverse), expected);
+    }
+}
+
+fn calculate_packet_loss(durations: &[Option<Duration>]) -> f64 {
+    if durations.is_empty() {
+        return 0.0;
+    }
+
+    let lost = durations.iter().filter(|d| d.is_none()).count();
+    let loss = lost as f64 / durations.len() as f64 * 100.0;
+    debug!(
+        "Lost pings: {} of {}, packet loss: {}%",
+        lost,
+        durations.len(),
+        loss
+    );
+
+    loss
+}
+
+#[cfg(test)]
+mod calculate_packet_loss_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_with_zero_durations() {
+        assert_eq!(calculate_packet_loss(&[]), 0.0);
+    }
+
+    #[test]
+    fn test_with_no_loss() {
+        let durations = &[
+            Some(Duration::from_nanos(100_000_000)),
+            Some(Duration::from_nanos(100_100_000)),
+        ];
+
+        assert_eq!(calculate_packet_loss(durations), 0.0);
+    }
+
+    #[test]
+    fn test_with_partial_loss() {
+        let durations = &[
+            Some(Duration::from_nanos(100_000_000)),
+            None,
+            Some(Duration::from_nanos(100_100_000)),
+            Some(Duration::from_nanos(100_200_000)),
+        ];
+
+        assert_eq!(calculate_packet_loss(durations), 25.0);
+    }
+
+    #[test]
+    fn test_with_total_loss() {
+        let durations = &[None, None, None];
+
+        assert_eq!(calculate_packet_loss(durations), 100.0);
+    }
 }
 
 fn calculate_deltas(durations: &[Duration]) -> Result<Vec<Duration>, CheckJitterError> {
@@ -906,9 +2169,82 @@ mod calculate_deltas_tests {
     }
 }
 
-fn calculate_avg_jitter(deltas: Vec<Duration>) -> f64 {
-    let total_jitter = deltas.iter().sum::<Duration>();
-    debug!("Sum of deltas: {:?}", total_jitter);
+/// Calculate the deltas between consecutive pings that both received a reply.
+fn calculate_surviving_deltas(
+    durations: &[Option<Duration>],
+) -> Result<Vec<Duration>, CheckJitterError> {
+    let mut deltas = Vec::new();
+
+    for run in durations.split(|d| d.is_none()) {
+        if run.len() < 2 {
+            continue;
+        }
+        let run: Vec<Duration> = run.iter().flatten().copied().collect();
+        deltas.extend(calculate_deltas(&run)?);
+    }
+
+    if deltas.is_empty() {
+        return Err(CheckJitterError::EmptyDeltas);
+    }
+
+    Ok(deltas)
+}
+
+#[cfg(test)]
+mod calculate_surviving_deltas_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_without_loss() {
+        let durations = &[
+            Some(Duration::from_nanos(100_000_000)),
+            Some(Duration::from_nanos(100_100_000)),
+            Some(Duration::from_nanos(100_300_000)),
+        ];
+
+        let expected_deltas = &[Duration::from_nanos(100_000), Duration::from_nanos(200_000)];
+
+        let deltas = calculate_surviving_deltas(durations).unwrap();
+
+        assert_eq!(deltas, expected_deltas);
+    }
+
+    #[test]
+    fn test_with_lost_ping_in_the_middle() {
+        let durations = &[
+            Some(Duration::from_nanos(100_000_000)),
+            Some(Duration::from_nanos(100_100_000)),
+            None,
+            Some(Duration::from_nanos(100_900_000)),
+            Some(Duration::from_nanos(100_600_000)),
+        ];
+
+        let expected_deltas = &[Duration::from_nanos(100_000), Duration::from_nanos(300_000)];
+
+        let deltas = calculate_surviving_deltas(durations).unwrap();
+
+        assert_eq!(deltas, expected_deltas);
+    }
+
+    #[test]
+    fn test_with_no_consecutive_replies() {
+        let durations = &[
+            Some(Duration::from_nanos(100_000_000)),
+            None,
+            Some(Duration::from_nanos(100_100_000)),
+            None,
+        ];
+
+        let result = calculate_surviving_deltas(durations);
+
+        assert_eq!(result, Err(CheckJitterError::EmptyDeltas));
+    }
+}
+
+fn calculate_avg_jitter(deltas: Vec<Duration>) -> f64 {
+    let total_jitter = deltas.iter().sum::<Duration>();
+    debug!("Sum of deltas: {:?}", total_jitter);
 
     let avg_jitter = total_jitter / deltas.len() as u32;
     debug!("Average jitter duration: {:?}", avg_jitter);
@@ -919,11 +2255,17 @@ fn calculate_avg_jitter(deltas: Vec<Duration>) -> f64 {
     average_float
 }
 
-fn calculate_median_jitter(deltas: Vec<Duration>) -> f64 {
-    let mut sorted_deltas = deltas.clone();
+fn sort_deltas(deltas: Vec<Duration>) -> Vec<Duration> {
+    let mut sorted_deltas = deltas;
     sorted_deltas.sort();
     debug!("Sorted deltas: {:?}", sorted_deltas);
 
+    sorted_deltas
+}
+
+fn calculate_median_jitter(deltas: Vec<Duration>) -> f64 {
+    let sorted_deltas = sort_deltas(deltas);
+
     let len = sorted_deltas.len();
     debug!("Number of deltas: {}", len);
 
@@ -943,6 +2285,146 @@ fn calculate_median_jitter(deltas: Vec<Duration>) -> f64 {
     median_float
 }
 
+/// Calculate the given percentile of the deltas.
+///
+/// The percentile is linearly interpolated between the two closest ranks, with the rank given by
+/// `p / 100 * (n - 1)`. This makes the 50th percentile equal to the median.
+fn calculate_percentile_jitter(deltas: Vec<Duration>, p: f64) -> Result<f64, CheckJitterError> {
+    if deltas.is_empty() {
+        return Err(CheckJitterError::EmptyDeltas);
+    }
+
+    let sorted_deltas = sort_deltas(deltas);
+    let percentile_float = interpolate_percentile(&sorted_deltas, p);
+    debug!("P{} jitter as f64: {:?}", p, percentile_float);
+
+    Ok(percentile_float)
+}
+
+fn interpolate_percentile(sorted: &[Duration], p: f64) -> f64 {
+    let rank = p / 100.0 * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    debug!(
+        "Percentile {} rank: {}, between {} and {}",
+        p, rank, lower, upper
+    );
+
+    let lower_float = sorted[lower].as_secs_f64() * 1_000.0;
+    let upper_float = sorted[upper].as_secs_f64() * 1_000.0;
+    lower_float + (upper_float - lower_float) * (rank - lower as f64)
+}
+
+#[cfg(test)]
+mod calculate_percentile_jitter_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn deltas_from_millis(millis: &[u64]) -> Vec<Duration> {
+        millis.iter().map(|m| Duration::from_millis(*m)).collect()
+    }
+
+    #[test]
+    fn test_with_zero_deltas() {
+        let result = calculate_percentile_jitter(vec![], 95.0);
+
+        assert_eq!(result, Err(CheckJitterError::EmptyDeltas));
+    }
+
+    #[test]
+    fn test_with_one_delta() {
+        let deltas = deltas_from_millis(&[7]);
+
+        assert_eq!(calculate_percentile_jitter(deltas, 95.0), Ok(7.0));
+    }
+
+    #[test]
+    fn test_with_exact_rank() {
+        let deltas = deltas_from_millis(&[5, 1, 4, 2, 3]);
+
+        assert_eq!(calculate_percentile_jitter(deltas.clone(), 0.0), Ok(1.0));
+        assert_eq!(calculate_percentile_jitter(deltas.clone(), 25.0), Ok(2.0));
+        assert_eq!(calculate_percentile_jitter(deltas.clone(), 75.0), Ok(4.0));
+        assert_eq!(calculate_percentile_jitter(deltas, 100.0), Ok(5.0));
+    }
+
+    #[test]
+    fn test_with_interpolated_rank() {
+        let deltas = deltas_from_millis(&[10, 20, 30, 40, 50, 60, 70, 80, 90, 100]);
+
+        let p90 = calculate_percentile_jitter(deltas.clone(), 90.0).unwrap();
+        let p95 = calculate_percentile_jitter(deltas, 95.0).unwrap();
+
+        assert_eq!(round_jitter(p90, 6), 91.0);
+        assert_eq!(round_jitter(p95, 6), 95.5);
+    }
+
+    #[test]
+    fn test_p50_equals_median() {
+        let deltas = deltas_from_millis(&[3, 9, 1, 12, 4, 8]);
+
+        let p50 = calculate_percentile_jitter(deltas.clone(), 50.0).unwrap();
+        let median = calculate_median_jitter(deltas);
+
+        assert_eq!(p50, median);
+    }
+}
+
+/// Calculate the interquartile range of the round trip times.
+///
+/// Unlike the other aggregation methods, this operates on the durations rather than the deltas
+/// between consecutive durations, measuring the spread of the delay (PDV) instead of the
+/// variation between consecutive packets (IPDV).
+fn calculate_iqr_jitter(durations: Vec<Duration>) -> Result<f64, CheckJitterError> {
+    if durations.is_empty() {
+        return Err(CheckJitterError::InsufficientSamples(0));
+    }
+
+    let mut sorted_durations = durations;
+    sorted_durations.sort();
+    debug!("Sorted durations: {:?}", sorted_durations);
+
+    let q1 = interpolate_percentile(&sorted_durations, 25.0);
+    let q3 = interpolate_percentile(&sorted_durations, 75.0);
+    let iqr_float = q3 - q1;
+    debug!("IQR jitter as f64: {:?}", iqr_float);
+
+    Ok(iqr_float)
+}
+
+#[cfg(test)]
+mod calculate_iqr_jitter_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_with_zero_durations() {
+        let result = calculate_iqr_jitter(vec![]);
+
+        assert_eq!(result, Err(CheckJitterError::InsufficientSamples(0)));
+    }
+
+    #[test]
+    fn test_with_one_duration() {
+        let durations = vec![Duration::from_millis(10)];
+
+        assert_eq!(calculate_iqr_jitter(durations), Ok(0.0));
+    }
+
+    #[test]
+    fn test_with_irregular_durations() {
+        let durations = vec![
+            Duration::from_millis(9),
+            Duration::from_millis(1),
+            Duration::from_millis(7),
+            Duration::from_millis(3),
+            Duration::from_millis(5),
+        ];
+
+        assert_eq!(calculate_iqr_jitter(durations), Ok(4.0));
+    }
+}
+
 fn calculate_max_jitter(deltas: Vec<Duration>) -> Result<f64, CheckJitterError> {
     let max = deltas.iter().max().ok_or(CheckJitterError::EmptyDeltas)?;
     debug!("Max jitter: {:?}", max);
@@ -961,6 +2443,152 @@ fn calculate_min_jitter(deltas: Vec<Duration>) -> Result<f64, CheckJitterError>
     Ok(min_float)
 }
 
+fn calculate_rtt_statistics(durations: &[Duration]) -> Result<RttStatistics, CheckJitterError> {
+    if durations.is_empty() {
+        return Err(CheckJitterError::InsufficientSamples(0));
+    }
+
+    let rtts: Vec<f64> = durations
+        .iter()
+        .map(|d| d.as_secs_f64() * 1_000.0)
+        .collect();
+    let count = rtts.len() as f64;
+
+    let min = rtts.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = rtts.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let avg = rtts.iter().sum::<f64>() / count;
+    let variance = rtts.iter().map(|r| (r - avg).powi(2)).sum::<f64>() / count;
+    let mdev = variance.sqrt();
+
+    let stats = RttStatistics {
+        min,
+        avg,
+        max,
+        mdev,
+    };
+    debug!("RTT statistics: {:?}", stats);
+
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod calculate_rtt_statistics_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_with_zero_durations() {
+        let result = calculate_rtt_statistics(&[]);
+
+        assert_eq!(result, Err(CheckJitterError::InsufficientSamples(0)));
+    }
+
+    #[test]
+    fn test_with_one_duration() {
+        let durations = &[Duration::from_millis(10)];
+
+        let stats = calculate_rtt_statistics(durations).unwrap();
+
+        assert_eq!(stats.min, 10.0);
+        assert_eq!(stats.avg, 10.0);
+        assert_eq!(stats.max, 10.0);
+        assert_eq!(stats.mdev, 0.0);
+    }
+
+    #[test]
+    fn test_with_irregular_durations() {
+        let durations = &[
+            Duration::from_millis(2),
+            Duration::from_millis(4),
+            Duration::from_millis(4),
+            Duration::from_millis(4),
+            Duration::from_millis(5),
+            Duration::from_millis(5),
+            Duration::from_millis(7),
+            Duration::from_millis(9),
+        ];
+
+        let stats = calculate_rtt_statistics(durations).unwrap();
+
+        assert_eq!(round_jitter(stats.min, 6), 2.0);
+        assert_eq!(round_jitter(stats.avg, 6), 5.0);
+        assert_eq!(round_jitter(stats.max, 6), 9.0);
+        assert_eq!(round_jitter(stats.mdev, 6), 2.0);
+    }
+}
+
+/// Calculate the interarrival jitter estimate described in RFC 3550, section 6.4.1.
+///
+/// Each delta updates the running estimate with a gain of 1/16, J += (|D| - J) / 16, starting
+/// from zero. The value after the last delta is returned.
+fn calculate_rfc3550_jitter(deltas: Vec<Duration>) -> Result<f64, CheckJitterError> {
+    if deltas.is_empty() {
+        return Err(CheckJitterError::EmptyDeltas);
+    }
+
+    let rfc3550_float = deltas.iter().fold(0.0, |j, d| {
+        let d_float = d.as_secs_f64() * 1_000.0;
+        j + (d_float - j) / 16.0
+    });
+    debug!("RFC 3550 jitter as f64: {:?}", rfc3550_float);
+
+    Ok(rfc3550_float)
+}
+
+#[cfg(test)]
+mod calculate_rfc3550_jitter_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    // The integer implementation from RFC 3550, appendix A.8, with the deltas in nanoseconds.
+    fn rfc3550_reference(deltas: &[Duration]) -> f64 {
+        let mut jitter: i64 = 0;
+        for d in deltas {
+            let d = d.as_nanos() as i64;
+            jitter += d - ((jitter + 8) >> 4);
+        }
+        (jitter >> 4) as f64 / 1_000_000.0
+    }
+
+    #[test]
+    fn test_with_zero_deltas() {
+        let result = calculate_rfc3550_jitter(vec![]);
+
+        assert_eq!(result, Err(CheckJitterError::EmptyDeltas));
+    }
+
+    #[test]
+    fn test_with_constant_deltas() {
+        let deltas = vec![Duration::from_millis(16), Duration::from_millis(16)];
+
+        let jitter = calculate_rfc3550_jitter(deltas).unwrap();
+
+        assert_eq!(jitter, 1.9375);
+    }
+
+    #[test]
+    fn test_with_irregular_durations() {
+        let irregular_durations = vec![
+            Duration::from_nanos(270_279_792),
+            Duration::from_nanos(270_400_049),
+            Duration::from_nanos(270_242_514),
+            Duration::from_nanos(269_988_869),
+            Duration::from_nanos(270_157_314),
+            Duration::from_nanos(270_096_136),
+            Duration::from_nanos(270_105_637),
+            Duration::from_nanos(270_003_857),
+            Duration::from_nanos(270_192_099),
+            Duration::from_nanos(270_035_557),
+        ];
+        let deltas = calculate_deltas(&irregular_durations).unwrap();
+
+        let expected = round_jitter(rfc3550_reference(&deltas), 5);
+        let actual = round_jitter(calculate_rfc3550_jitter(deltas).unwrap(), 5);
+
+        assert_eq!(actual, expected);
+    }
+}
+
 /// Round the jitter to the specified precision.
 pub fn round_jitter(j: f64, precision: u8) -> f64 {
     let factor = 10f64.powi(precision as i32);
@@ -1086,130 +2714,753 @@ mod calculate_rounded_jitter_tests {
 /// Get and calculate the aggregated jitter to an IP address or hostname.
 ///
 /// This function will perform a DNS lookup if a hostname is provided and then use that IP address
-/// to ping the target. The function will then calculate the aggregated value based on the
-/// aggregation method passed as an argument. This value will then be rounded to the specified
-/// decimal.
+/// to ping the target. The function will then calculate the aggregated value for each of the
+/// aggregation methods passed as an argument, all from the same samples.
+///
+/// Pings that time out are counted as lost and do not abort the measurement. The jitter is
+/// calculated from the consecutive pings that both received a reply, and the share of lost
+/// pings is reported as the packet loss. The round trip times of the received replies are
+/// summarized in the `RttStatistics` of the measurement. The packet loss is always measured,
+/// even if too few replies were received to calculate the jitter or round trip times.
 ///
-/// Note that opening a raw socket requires root privileges on Unix-like systems.
+/// Probe types that measure parts of the round trip separately, such as the forward and reverse
+/// delays of `SocketType::Twamp` and `SocketType::Timestamp` or the steps of an HTTP request with
+/// `SocketType::Http`, also report the jitter of each part.
+///
+/// Note that opening a raw socket requires root privileges on Unix-like systems. Using
+/// `SocketType::Tcp` measures the time of TCP handshakes instead, which does not require any
+/// privileges.
 ///
 /// # Arguments
-/// * `aggr_method` - The aggregation method to use.
+/// * `aggr_methods` - The aggregation methods to use.
 /// * `addr` - The IP address or hostname to ping.
+/// * `family` - The IP version of the resolved addresses to use.
 /// * `socket_type` - The type of socket to use for the ping.
+/// * `options` - The options of the socket, such as the source address.
 /// * `samples` - The number of samples (pings) to take.
 /// * `timeout` - The timeout for each ping.
 /// * `min_interval` - The minimum interval between pings in milliseconds.
 /// * `max_interval` - The maximum interval between pings in milliseconds.
 ///
 /// # Returns
-/// The aggregated jitter in milliseconds as a floating point number rounded to the
-/// specified decimal.
+/// A `Measurement` with the aggregated jitter in milliseconds, the packet loss in percent and the
+/// round trip time statistics in milliseconds.
 ///
 /// # Example
 /// ```rust,no_run
 /// // This example will not run because it requires root privileges.
-/// use check_jitter::{get_jitter, CheckJitterError, AggregationMethod, SocketType};
+/// use check_jitter::{
+///     get_jitter, AddressFamily, AggregationMethod, CheckJitterError, SocketOptions, SocketType,
+/// };
 /// use std::time::Duration;
 ///
-/// let jitter = get_jitter(
-///     AggregationMethod::Average, // aggr_method
-///     "192.168.1.1",              // addr
-///     SocketType::Raw,            // socket_type
-///     10,                         // samples
-///     Duration::from_secs(1),     // timeout
-///     10,                         // min_interval
-///     100).unwrap();              // max_interval
-/// println!("Average jitter: {}ms", jitter);
+/// let measurement = get_jitter(
+///     &[AggregationMethod::Average, AggregationMethod::Max], // aggr_methods
+///     "192.168.1.1",                                         // addr
+///     AddressFamily::Any,                                    // family
+///     SocketType::Raw,                                       // socket_type
+///     &SocketOptions::default(),                             // options
+///     10,                                                    // samples
+///     Duration::from_secs(1),                                // timeout
+///     10,                                                    // min_interval
+///     100).unwrap();                                         // max_interval
+/// for (aggr_method, jitter) in &measurement.jitter {
+///     match jitter {
+///         Some(jitter) => println!("{} jitter: {}ms", aggr_method, jitter),
+///         None => println!("{} jitter: too few replies", aggr_method),
+///     }
+/// }
+/// println!("Packet loss: {}%", measurement.packet_loss);
 /// ```
+#[allow(clippy::too_many_arguments)]
 pub fn get_jitter(
-    aggr_method: AggregationMethod,
+    aggr_methods: &[AggregationMethod],
     addr: &str,
+    family: AddressFamily,
     socket_type: SocketType,
+    options: &SocketOptions,
     samples: u8,
     timeout: Duration,
     min_interval: u64,
     max_interval: u64,
-) -> Result<f64, CheckJitterError> {
-    let durations = get_durations(
+) -> Result<Measurement, CheckJitterError> {
+    let samples = get_durations(
         addr,
+        family,
         socket_type,
+        options,
         samples,
         timeout,
         min_interval,
         max_interval,
     )?;
-    let deltas = calculate_deltas(&durations)?;
-    match aggr_method {
-        AggregationMethod::Average => Ok(calculate_avg_jitter(deltas)),
-        AggregationMethod::Median => Ok(calculate_median_jitter(deltas)),
-        AggregationMethod::Max => calculate_max_jitter(deltas),
-        AggregationMethod::Min => calculate_min_jitter(deltas),
+
+    measure(aggr_methods, &samples)
+}
+
+/// An address with its `Measurement`, or the error that its measurement failed with.
+pub type AddressMeasurement = (IpAddr, Result<Measurement, CheckJitterError>);
+
+/// Get the jitter of every address that `addr` resolves to, in the order they were resolved.
+///
+/// Each address is measured in turn as described for `get_jitter`, with the same arguments. The
+/// failure of one address does not stop the others from being measured. A deadline in `options`
+/// is shared between the addresses, see `address_deadline`.
+///
+/// # Returns
+/// Each resolved address with its `Measurement`, or the error that its measurement failed with.
+/// An error is only returned for the whole call if the host could not be resolved.
+#[allow(clippy::too_many_arguments)]
+pub fn get_jitter_per_address(
+    aggr_methods: &[AggregationMethod],
+    addr: &str,
+    family: AddressFamily,
+    socket_type: SocketType,
+    options: &SocketOptions,
+    samples: u8,
+    timeout: Duration,
+    min_interval: u64,
+    max_interval: u64,
+) -> Result<Vec<AddressMeasurement>, CheckJitterError> {
+    let mut ips = parse_addr(addr, family)?;
+    // The resolver may return the same address once per socket type.
+    let mut seen = Vec::with_capacity(ips.len());
+    ips.retain(|ip| {
+        let new = !seen.contains(ip);
+        seen.push(*ip);
+        new
+    });
+    if ips.is_empty() {
+        return Err(CheckJitterError::DnsLookupFailed(addr.to_string()));
+    }
+    debug!("Probing resolved addresses: {:?}", ips);
+
+    let mut addresses_left = ips.len();
+    Ok(measure_addresses(aggr_methods, ips, |ip| {
+        let options = SocketOptions {
+            deadline: options
+                .deadline
+                .map(|d| address_deadline(d, addresses_left)),
+            ..options.clone()
+        };
+        addresses_left -= 1;
+        get_durations_for_ip(
+            ip,
+            socket_type.clone(),
+            &options,
+            samples,
+            timeout,
+            min_interval,
+            max_interval,
+        )
+    }))
+}
+
+/// The deadline for the next of `addresses_left` addresses to be measured, so that the time left
+/// until `deadline` is split evenly between them. Time that an address does not use is shared by
+/// the addresses after it.
+fn address_deadline(deadline: Instant, addresses_left: usize) -> Instant {
+    let now = Instant::now();
+    let share = deadline.saturating_duration_since(now) / addresses_left.max(1) as u32;
+    now + share
+}
+
+#[cfg(test)]
+mod address_deadline_tests {
+    use super::*;
+
+    #[test]
+    fn test_splits_time_left() {
+        let deadline = Instant::now() + Duration::from_secs(4);
+
+        let first = address_deadline(deadline, 2);
+
+        assert!(first <= Instant::now() + Duration::from_secs(2));
+        assert!(first > Instant::now() + Duration::from_secs(1));
+        assert_eq!(address_deadline(deadline, 1), deadline);
+    }
+
+    #[test]
+    fn test_with_passed_deadline() {
+        let deadline = Instant::now();
+
+        assert!(address_deadline(deadline, 3) <= Instant::now());
+    }
+}
+
+/// Measure each of the addresses in turn with the samples taken by `sample`.
+fn measure_addresses<F>(
+    aggr_methods: &[AggregationMethod],
+    ips: Vec<IpAddr>,
+    mut sample: F,
+) -> Vec<AddressMeasurement>
+where
+    F: FnMut(IpAddr) -> Result<Samples, CheckJitterError>,
+{
+    ips.into_iter()
+        .map(|ip| {
+            let measurement = sample(ip).and_then(|samples| measure(aggr_methods, &samples));
+            if let Err(e) = &measurement {
+                info!("Measuring {} failed: {}", ip, e);
+            }
+            (ip, measurement)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod measure_addresses_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_with_failed_address() {
+        let v4 = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let v6 = IpAddr::V6(Ipv6Addr::LOCALHOST);
+
+        let measurements =
+            measure_addresses(&[AggregationMethod::Average], vec![v6, v4], |ip| match ip {
+                IpAddr::V4(_) => Ok(Samples {
+                    replies: [10, 12, 11]
+                        .iter()
+                        .map(|ms| Some(Reply::from(Duration::from_millis(*ms))))
+                        .collect(),
+                    sequence_errors: None,
+                }),
+                IpAddr::V6(_) => Err(CheckJitterError::PermissionDenied),
+            });
+
+        assert_eq!(measurements.len(), 2);
+        assert_eq!(
+            measurements[0],
+            (v6, Err(CheckJitterError::PermissionDenied))
+        );
+        assert_eq!(measurements[1].0, v4);
+        assert_eq!(
+            measurements[1].1.as_ref().unwrap().jitter,
+            vec![(AggregationMethod::Average, Some(1.5))]
+        );
+    }
+}
+
+fn measure(
+    aggr_methods: &[AggregationMethod],
+    samples: &Samples,
+) -> Result<Measurement, CheckJitterError> {
+    let replies = &samples.replies;
+    let durations: Vec<Option<Duration>> =
+        replies.iter().map(|r| r.as_ref().map(|r| r.rtt)).collect();
+
+    let packet_loss = calculate_packet_loss(&durations);
+    let received: Vec<Duration> = durations.iter().flatten().copied().collect();
+    let rtt = if received.is_empty() {
+        None
+    } else {
+        Some(calculate_rtt_statistics(&received)?)
+    };
+    let jitter = aggregate_jitter(aggr_methods, &durations)?;
+
+    let mut component_jitter = Vec::new();
+    for component in Component::ALL {
+        let component_durations = component_durations(replies, component);
+        if component_durations.iter().all(|d| d.is_none()) {
+            continue;
+        }
+        for (aggr_method, value) in aggregate_jitter(aggr_methods, &component_durations)? {
+            component_jitter.push((component, aggr_method, value));
+        }
+    }
+
+    Ok(Measurement {
+        jitter,
+        component_jitter,
+        packet_loss,
+        rtt,
+        sequence_errors: samples.sequence_errors,
+    })
+}
+
+#[cfg(test)]
+mod measure_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn samples(rtts: &[Option<u64>]) -> Samples {
+        Samples {
+            replies: rtts
+                .iter()
+                .map(|rtt| rtt.map(|ms| Reply::from(Duration::from_millis(ms))))
+                .collect(),
+            sequence_errors: None,
+        }
+    }
+
+    #[test]
+    fn test_with_full_loss() {
+        let aggr_methods = [AggregationMethod::Average, AggregationMethod::StdDev];
+
+        let measurement = measure(&aggr_methods, &samples(&[None, None, None])).unwrap();
+
+        let expected = Measurement {
+            jitter: vec![
+                (AggregationMethod::Average, None),
+                (AggregationMethod::StdDev, None),
+            ],
+            component_jitter: Vec::new(),
+            packet_loss: 100.0,
+            rtt: None,
+            sequence_errors: None,
+        };
+        assert_eq!(measurement, expected);
+    }
+
+    #[test]
+    fn test_with_alternating_loss() {
+        let aggr_methods = [AggregationMethod::Average, AggregationMethod::StdDev];
+        let samples = samples(&[Some(10), None, Some(14), None]);
+
+        let measurement = measure(&aggr_methods, &samples).unwrap();
+
+        assert_eq!(measurement.packet_loss, 50.0);
+        assert_eq!(
+            measurement.jitter,
+            vec![
+                (AggregationMethod::Average, None),
+                (AggregationMethod::StdDev, Some(2.0)),
+            ]
+        );
+        assert_eq!(measurement.rtt.map(|r| r.avg), Some(12.0));
+    }
+
+    #[test]
+    fn test_with_partial_loss() {
+        let samples = samples(&[Some(10), Some(12), None, Some(14), Some(15)]);
+
+        let measurement = measure(&[AggregationMethod::Average], &samples).unwrap();
+
+        assert_eq!(
+            measurement.jitter,
+            vec![(AggregationMethod::Average, Some(1.5))]
+        );
+        assert_eq!(measurement.packet_loss, 20.0);
+    }
+}
+
+/// Aggregate the durations, some of which may be lost, with each of the aggregation methods.
+///
+/// The jitter of a method is `None` if too few durations were received to calculate it: no two
+/// consecutive durations for the methods that aggregate deltas, or none at all for the others.
+fn aggregate_jitter(
+    aggr_methods: &[AggregationMethod],
+    durations: &[Option<Duration>],
+) -> Result<Vec<(AggregationMethod, Option<f64>)>, CheckJitterError> {
+    let received: Vec<Duration> = durations.iter().flatten().copied().collect();
+    let deltas = if aggr_methods.iter().any(|m| m.uses_deltas()) {
+        match calculate_surviving_deltas(durations) {
+            Ok(deltas) => Some(deltas),
+            Err(CheckJitterError::EmptyDeltas) => None,
+            Err(e) => return Err(e),
+        }
+    } else {
+        None
+    };
+
+    let mut jitter = Vec::with_capacity(aggr_methods.len());
+    for aggr_method in aggr_methods {
+        let value = match (*aggr_method, &deltas) {
+            (m, None) if m.uses_deltas() => None,
+            (_, _) if received.is_empty() => None,
+            (AggregationMethod::Average, Some(d)) => Some(calculate_avg_jitter(d.clone())),
+            (AggregationMethod::Median, Some(d)) => Some(calculate_median_jitter(d.clone())),
+            (AggregationMethod::Max, Some(d)) => Some(calculate_max_jitter(d.clone())?),
+            (AggregationMethod::Min, Some(d)) => Some(calculate_min_jitter(d.clone())?),
+            (AggregationMethod::Percentile(p), Some(d)) => {
+                Some(calculate_percentile_jitter(d.clone(), p)?)
+            }
+            (AggregationMethod::Rfc3550, Some(d)) => Some(calculate_rfc3550_jitter(d.clone())?),
+            // The standard deviation of the durations is the same as the RTT mdev.
+            (AggregationMethod::StdDev, _) => Some(calculate_rtt_statistics(&received)?.mdev),
+            (AggregationMethod::Iqr, _) => Some(calculate_iqr_jitter(received.clone())?),
+            (_, None) => None,
+        };
+        jitter.push((*aggr_method, value));
+    }
+
+    Ok(jitter)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum State {
+    Ok,
+    Warning,
+    Critical,
+}
+
+fn evaluate_perfdata(p: &Perfdata) -> State {
+    info!("Evaluating {}: {:?}", p.metric, p.value);
+    if let Some(c) = p.thresholds.critical {
+        info!("Checking critical threshold: {:?}", c);
+        if c.check(p.value) {
+            info!("{} is critical: {:?}", p.metric, p.value);
+            return State::Critical;
+        } else {
+            info!("{} is not critical: {:?}", p.metric, p.value);
+        }
+    } else {
+        info!("No critical threshold provided for {}", p.metric);
+    }
+
+    if let Some(w) = p.thresholds.warning {
+        info!("Checking warning threshold: {:?}", w);
+        if w.check(p.value) {
+            info!("{} is warning: {:?}", p.metric, p.value);
+            return State::Warning;
+        } else {
+            info!("{} is not warning: {:?}", p.metric, p.value);
+        }
+    } else {
+        info!("No warning threshold provided for {}", p.metric);
     }
+
+    State::Ok
 }
 
-/// Evaluate the jitter against the thresholds and return the appropriate status.
+/// Evaluate the measured values against their thresholds and return the appropriate status.
 ///
-/// This function will evaluate the jitter against the provided thresholds and return the
-/// appropriate status. It will match against the critical threshold first and then the warning
-/// threshold, returning the first match or `Status::Ok` if no thresholds are matched.
+/// Each value is matched against its critical threshold first and then its warning threshold.
+/// The worst state across all values decides the returned status, which is `Status::Ok` if no
+/// thresholds are matched.
 ///
 /// # Arguments
-/// * `jitter` - The jitter to evaluate as a 64 bit floating point number.
-/// * `thresholds` - A reference to the `Thresholds` to evaluate against.
+/// * `perfdata` - The measured values, each with the `Thresholds` to evaluate it against.
 ///
 /// # Returns
-/// The `Status` of the jitter against the thresholds.
+/// The `Status` of the values against the thresholds.
 ///
 /// # Example
 /// ```rust
-/// use check_jitter::{evaluate_thresholds, AggregationMethod, Thresholds, Status};
+/// use check_jitter::{evaluate_thresholds, AggregationMethod, Metric, Perfdata, Status, Thresholds};
 /// use nagios_range::NagiosRange as ThresholdRange;
-/// use std::time::Duration;
 ///
-/// let jitter = 0.1;
-/// let thresholds = Thresholds {
-///     warning: Some(ThresholdRange::from("0:0.5").unwrap()),
-///     critical: Some(ThresholdRange::from("0:1").unwrap()),
+/// let jitter = Perfdata {
+///     metric: Metric::Jitter(AggregationMethod::Average),
+///     value: 0.1,
+///     thresholds: Thresholds {
+///         warning: Some(ThresholdRange::from("0:0.5").unwrap()),
+///         critical: Some(ThresholdRange::from("0:1").unwrap()),
+///     },
+///     address: None,
+///     dscp: None,
 /// };
 ///
-/// let status = evaluate_thresholds(AggregationMethod::Average, jitter, &thresholds);
+/// let status = evaluate_thresholds(vec![jitter]);
 ///
 /// match status {
-///     Status::Ok(_, _, _) => println!("Jitter is OK"),
-///     Status::Warning(_, _, _) => println!("Jitter is warning"),
-///     Status::Critical(_, _, _) => println!("Jitter is critical"),
+///     Status::Ok(_) => println!("Jitter is OK"),
+///     Status::Warning(_) => println!("Jitter is warning"),
+///     Status::Critical(_) => println!("Jitter is critical"),
 ///     Status::Unknown(_) => println!("Unknown status"),
 /// }
 /// ```
-pub fn evaluate_thresholds(
-    aggr_method: AggregationMethod,
-    value: f64,
-    thresholds: &Thresholds,
+pub fn evaluate_thresholds(perfdata: Vec<Perfdata>) -> Status {
+    let state = perfdata
+        .iter()
+        .map(evaluate_perfdata)
+        .max()
+        .unwrap_or(State::Ok);
+
+    status_with_state(state, perfdata)
+}
+
+fn status_with_state(state: State, perfdata: Vec<Perfdata>) -> Status {
+    match state {
+        State::Ok => Status::Ok(perfdata),
+        State::Warning => Status::Warning(perfdata),
+        State::Critical => Status::Critical(perfdata),
+    }
+}
+
+/// How the states of several addresses are combined into the state of the check.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum OverallState {
+    /// The check has the worst state of any address.
+    #[default]
+    Worst,
+    /// The check has the best state of any address.
+    Best,
+}
+
+impl std::str::FromStr for OverallState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "worst" => Ok(OverallState::Worst),
+            "best" => Ok(OverallState::Best),
+            _ => Err(format!("'{}' is not one of: worst, best", s)),
+        }
+    }
+}
+
+impl fmt::Display for OverallState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OverallState::Worst => write!(f, "Worst"),
+            OverallState::Best => write!(f, "Best"),
+        }
+    }
+}
+
+/// Evaluate values measured for several addresses against their thresholds.
+///
+/// Each address gets the worst state of its own values, as with `evaluate_thresholds`, or is
+/// UNKNOWN if it could not be measured. The state of the check is then the worst or the best
+/// state of any address, depending on `overall`, where UNKNOWN ranks above CRITICAL. With
+/// `OverallState::Best` the check is therefore only UNKNOWN if no address could be measured. The
+/// values of all measured addresses are included in the returned status, also when it is UNKNOWN.
+pub fn evaluate_addresses(
+    addresses: Vec<(IpAddr, Result<Vec<Perfdata>, UnknownVariant>)>,
+    overall: OverallState,
 ) -> Status {
-    info!("Evaluating jitter: {:?}", value);
-    if let Some(c) = thresholds.critical {
-        info!("Checking critical threshold: {:?}", c);
-        if c.check(value) {
-            info!("Jitter is critical: {:?}", value);
-            return Status::Critical(aggr_method, value, thresholds);
-        } else {
-            info!("Jitter is not critical: {:?}", value);
+    let mut perfdata = Vec::new();
+    let mut states = Vec::new();
+    let mut failures = Vec::new();
+    for (address, result) in addresses {
+        match result {
+            Ok(p) => {
+                let state = p.iter().map(evaluate_perfdata).max().unwrap_or(State::Ok);
+                info!("State of {}: {:?}", address, state);
+                states.push(state);
+                perfdata.extend(p);
+            }
+            Err(e) => {
+                info!("State of {}: Unknown ({:?})", address, e);
+                failures.push(e);
+            }
         }
-    } else {
-        info!("No critical threshold provided");
     }
 
-    if let Some(w) = thresholds.warning {
-        info!("Checking warning threshold: {:?}", w);
-        if w.check(value) {
-            info!("Jitter is warning: {:?}", value);
-            return Status::Warning(aggr_method, value, thresholds);
-        } else {
-            info!("Jitter is not warning: {:?}", value);
+    let state = match overall {
+        OverallState::Worst if !failures.is_empty() => None,
+        OverallState::Worst => states.into_iter().max(),
+        OverallState::Best => states.into_iter().min(),
+    };
+
+    match (state, failures.into_iter().next()) {
+        (Some(state), _) => status_with_state(state, perfdata),
+        (None, Some(e)) if perfdata.is_empty() => Status::Unknown(e),
+        (None, Some(e)) => Status::Unknown(UnknownVariant::PartialFailure(Box::new(e), perfdata)),
+        (None, None) => Status::Ok(perfdata),
+    }
+}
+
+#[cfg(test)]
+mod evaluate_addresses_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn jitter(address: IpAddr, value: f64) -> Perfdata {
+        Perfdata {
+            metric: Metric::Jitter(AggregationMethod::Average),
+            value,
+            thresholds: Thresholds {
+                warning: Some(ThresholdRange::from("0:0.5").unwrap()),
+                critical: Some(ThresholdRange::from("0:1").unwrap()),
+            },
+            address: Some(address),
+            dscp: None,
         }
-    } else {
-        info!("No warning threshold provided");
     }
 
-    Status::Ok(aggr_method, value, thresholds)
+    fn v4() -> IpAddr {
+        IpAddr::V4(Ipv4Addr::LOCALHOST)
+    }
+
+    fn v6() -> IpAddr {
+        IpAddr::V6(Ipv6Addr::LOCALHOST)
+    }
+
+    fn addresses() -> Vec<(IpAddr, Result<Vec<Perfdata>, UnknownVariant>)> {
+        vec![
+            (v4(), Ok(vec![jitter(v4(), 0.1)])),
+            (v6(), Ok(vec![jitter(v6(), 2.0)])),
+        ]
+    }
+
+    fn with_failure() -> Vec<(IpAddr, Result<Vec<Perfdata>, UnknownVariant>)> {
+        vec![
+            (v4(), Ok(vec![jitter(v4(), 0.1)])),
+            (v6(), Err(UnknownVariant::MaxLossExceeded(100.0, 50.0))),
+        ]
+    }
+
+    #[test]
+    fn test_worst() {
+        let status = evaluate_addresses(addresses(), OverallState::Worst);
+
+        assert_eq!(
+            status,
+            Status::Critical(vec![jitter(v4(), 0.1), jitter(v6(), 2.0)])
+        );
+    }
+
+    #[test]
+    fn test_best() {
+        let status = evaluate_addresses(addresses(), OverallState::Best);
+
+        assert_eq!(
+            status,
+            Status::Ok(vec![jitter(v4(), 0.1), jitter(v6(), 2.0)])
+        );
+    }
+
+    #[test]
+    fn test_worst_with_failed_address() {
+        let status = evaluate_addresses(with_failure(), OverallState::Worst);
+
+        assert_eq!(
+            status,
+            Status::Unknown(UnknownVariant::PartialFailure(
+                Box::new(UnknownVariant::MaxLossExceeded(100.0, 50.0)),
+                vec![jitter(v4(), 0.1)]
+            ))
+        );
+        assert_eq!(
+            status.to_string(),
+            "UNKNOWN - Packet loss of 100% exceeds the maximum allowed loss of 50%\
+             |'127.0.0.1 Average Jitter'=0.1ms;0:0.5;0:1;0"
+        );
+    }
+
+    #[test]
+    fn test_best_with_failed_address() {
+        let status = evaluate_addresses(with_failure(), OverallState::Best);
+
+        assert_eq!(status, Status::Ok(vec![jitter(v4(), 0.1)]));
+    }
+
+    #[test]
+    fn test_best_with_all_addresses_failed() {
+        let addresses = vec![
+            (v4(), Err(UnknownVariant::UnmeasuredMetric(Metric::RttAvg))),
+            (v6(), Err(UnknownVariant::MaxLossExceeded(100.0, 50.0))),
+        ];
+
+        let status = evaluate_addresses(addresses, OverallState::Best);
+
+        assert_eq!(
+            status,
+            Status::Unknown(UnknownVariant::UnmeasuredMetric(Metric::RttAvg))
+        );
+    }
+
+    #[test]
+    fn test_labels() {
+        let status = evaluate_addresses(addresses(), OverallState::Best);
+
+        assert_eq!(
+            status.to_string(),
+            "OK - 127.0.0.1 Average Jitter: 0.1ms, ::1 Average Jitter: 2ms\
+             |'127.0.0.1 Average Jitter'=0.1ms;0:0.5;0:1;0 '::1 Average Jitter'=2ms;0:0.5;0:1;0"
+        );
+    }
+}
+
+#[cfg(test)]
+mod evaluate_thresholds_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn perfdata(metric: Metric, value: f64, warning: &str, critical: &str) -> Perfdata {
+        Perfdata {
+            metric,
+            value,
+            thresholds: Thresholds {
+                warning: Some(ThresholdRange::from(warning).unwrap()),
+                critical: Some(ThresholdRange::from(critical).unwrap()),
+            },
+            address: None,
+            dscp: None,
+        }
+    }
+
+    #[test]
+    fn test_with_all_ok() {
+        let p = vec![
+            perfdata(Metric::Jitter(AggregationMethod::Average), 0.1, "5", "10"),
+            perfdata(Metric::PacketLoss, 0.0, "1", "5"),
+        ];
+
+        assert_eq!(evaluate_thresholds(p.clone()), Status::Ok(p));
+    }
+
+    #[test]
+    fn test_with_warning_loss_and_ok_jitter() {
+        let p = vec![
+            perfdata(Metric::Jitter(AggregationMethod::Average), 0.1, "5", "10"),
+            perfdata(Metric::PacketLoss, 2.0, "1", "5"),
+        ];
+
+        assert_eq!(evaluate_thresholds(p.clone()), Status::Warning(p));
+    }
+
+    #[test]
+    fn test_with_critical_loss_and_warning_jitter() {
+        let p = vec![
+            perfdata(Metric::Jitter(AggregationMethod::Average), 6.0, "5", "10"),
+            perfdata(Metric::PacketLoss, 10.0, "1", "5"),
+        ];
+
+        assert_eq!(evaluate_thresholds(p.clone()), Status::Critical(p));
+    }
+
+    #[test]
+    fn test_with_critical_jitter_and_ok_loss() {
+        let p = vec![
+            perfdata(Metric::Jitter(AggregationMethod::Average), 11.0, "5", "10"),
+            perfdata(Metric::PacketLoss, 0.0, "1", "5"),
+        ];
+
+        assert_eq!(evaluate_thresholds(p.clone()), Status::Critical(p));
+    }
+
+    #[test]
+    fn test_with_loss_without_thresholds() {
+        let p = vec![
+            perfdata(Metric::Jitter(AggregationMethod::Average), 0.1, "5", "10"),
+            Perfdata {
+                metric: Metric::PacketLoss,
+                value: 50.0,
+                thresholds: Thresholds::default(),
+                address: None,
+                dscp: None,
+            },
+        ];
+
+        assert_eq!(evaluate_thresholds(p.clone()), Status::Ok(p));
+    }
+
+    #[test]
+    fn test_with_full_loss() {
+        let samples = Samples {
+            replies: vec![None, None, None],
+            sequence_errors: None,
+        };
+        let measurement = measure(&[AggregationMethod::Average], &samples).unwrap();
+        assert_eq!(measurement.jitter, vec![(AggregationMethod::Average, None)]);
+
+        let p = vec![perfdata(
+            Metric::PacketLoss,
+            measurement.packet_loss,
+            "1",
+            "50",
+        )];
+        let status = evaluate_thresholds(p.clone());
+
+        assert_eq!(status, Status::Critical(p));
+        assert_eq!(
+            status.to_string(),
+            "CRITICAL - Packet Loss: 100%|'Packet Loss'=100%;0:1;0:50;0;100"
+        );
+    }
 }
@@ -1,11 +1,11 @@
 use check_jitter::*;
 use chrono::Utc;
-use clap::{value_parser, ArgAction::Count, Parser};
+use clap::{value_parser, ArgAction::Count, ArgGroup, Parser};
 use log::{info, LevelFilter};
 use nagios_range::NagiosRange as ThresholdRange;
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::process;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 const ABOUT_TEXT: &str = r#"
 check_jitter - A monitoring plugin that measures network jitter.
@@ -17,18 +17,213 @@ The plugin can aggregate the deltas from multiple samples in the following ways:
 - median: the median of all deltas
 - max: the maximum of all deltas
 - min: the minimum of all deltas
+- pN or percentile:N: the Nth percentile of all deltas, e.g. p95 or percentile:99.9,
+  linearly interpolated between the closest ranks
+- rfc3550: the interarrival jitter estimate from RFC 3550, J += (|D| - J) / 16
+- stddev: the standard deviation of all round trip times
+- iqr: the interquartile range (p75 - p25) of all round trip times
+
+Several aggregation methods can be evaluated from the same samples by repeating
+-a or separating the methods with commas, e.g. -a average,median,max. Each method
+is reported as its own performance data label. The -w and -c thresholds are then
+given in the same order, e.g. -w 5,10,20, or as a single range for all methods.
+
+The methods based on deltas measure the variation between consecutive packets
+(IPDV), while stddev and iqr measure the spread of the delay itself (PDV).
+
+PACKET LOSS
+
+Pings that time out are counted as lost instead of aborting the check. The jitter
+is calculated from the consecutive pings that both received a reply, and the
+packet loss is reported alongside the jitter.
+
+Packet loss can be alerted on with --loss-warning and --loss-critical, using the
+same range syntax as the jitter thresholds. The worst state of the jitter and the
+packet loss decides the result of the check.
+
+If the packet loss exceeds --max-loss (in percent), the check returns UNKNOWN.
+Otherwise, values that cannot be calculated because too few replies were
+received are left out, e.g. the jitter when no two consecutive pings received a
+reply, or every value but the packet loss when all pings were lost.
+
+ROUND TRIP TIME
+
+The minimum, average, maximum and standard deviation (mdev) of the round trip
+times of the received replies are reported as performance data. The average
+round trip time can be alerted on with --rtt-warning and --rtt-critical.
 
 HOSTNAME
 
 If the hostname resolves to multiple IP addresses, the plugin will use the first
 address returned by the DNS resolver and skip the rest.
 
+Hosts with both IPv4 and IPv6 addresses are measured over whichever the resolver
+returns first. Use -4 or -6 to only use addresses of that IP version. The check
+returns UNKNOWN if the host has no address of the requested version.
+
+With --all-addresses, every resolved address is measured in turn and reported
+with its own performance data labels, e.g. '192.0.2.1 Average Jitter'. The
+thresholds apply to each address, and the state of the check is the worst state
+of any address, or the best with --overall-state best. An address that cannot
+be measured, or that exceeds --max-loss, is UNKNOWN without stopping the others.
+UNKNOWN ranks above CRITICAL, so with best the check is only UNKNOWN if no
+address could be measured. The values of the measured addresses are reported
+even when the check is UNKNOWN.
+
 RECOMMENDATION TO USE IP ADDRESSES
 
 While using a hostname is supported, consider using IP addresses instead. It's
 better to set up multiple tests to cover each IP individually rather than relying
 on hostname resolution.
 
+SOURCE ADDRESS AND INTERFACE
+
+On hosts with several uplinks, --source sets the local address the probes are
+sent from and --interface binds them to a network interface (SO_BINDTODEVICE,
+Linux only). The check returns UNKNOWN if the source address is not assigned to
+a local interface, is of another IP version than the target, or if binding is
+not permitted.
+
+DSCP MARKING
+
+Networks with QoS treat traffic differently depending on its marking. --dscp
+sets the DSCP code point of the probes in the TOS byte (IPv4) or traffic class
+(IPv6), given by name (CS0-CS7, AF11-AF43, EF, VA, LE) or as a value from 0 to
+63. The marking is added to the labels of the output, e.g.
+'Average Jitter (DSCP EF)', so that checks of differently marked traffic can be
+told apart.
+
+PAYLOAD SIZE
+
+Serialization delay on slow links makes jitter depend on the size of the
+packets. --payload-size sets the size of the payload of ICMP echo requests, UDP
+datagrams and TWAMP-Light test packets, e.g. about 200 bytes to emulate voice
+or 1200 bytes to emulate video. UDP probes need at least 8 bytes and TWAMP-Light
+probes at least 41. The payload is filled with random bytes, or with a repeated
+pattern given in hex with --payload-pattern.
+
+With --dont-fragment the probes are sent with the Don't Fragment bit set (Linux
+only), and the check returns UNKNOWN if a probe does not fit the path MTU.
+
+TIME TO LIVE
+
+--ttl sets the time to live (IPv4) or hop limit (IPv6) of the probes. In ICMP
+mode with a raw socket, the check returns UNKNOWN with the address of the
+reporting router if a time exceeded or destination unreachable message is
+received instead of an echo reply, e.g. when the target is further away than
+the given number of hops.
+
+KERNEL TIMESTAMPS
+
+On a busy host, the time until the plugin is scheduled to handle a reply adds to
+the measured round trip times and thereby to the jitter. With
+--kernel-timestamps, the send and receive times of ICMP pings are taken from the
+kernel (SO_TIMESTAMPING, Linux only) instead. Network cards that are configured
+to take hardware timestamps provide them, otherwise the kernel takes software
+timestamps. The difference from the times measured by the plugin itself is
+logged with -vvv.
+
+FIXED RATE
+
+By default, each ping is sent after the reply to the previous one, so the time
+between pings depends on the round trip time. With --fixed-rate MS, a ping is
+sent every MS milliseconds, e.g. 20 like RTP voice packets, while the replies are
+matched to the pings by their sequence numbers as they arrive. Each ping still
+waits up to --timeout for its reply, so several pings can be in flight at once.
+Fixed rate mode is only available for ICMP pings.
+
+DEADLINE
+
+--timeout only limits the wait for each reply, so a check with many samples,
+long intervals or lost replies can take longer than the service check timeout of
+the monitoring engine, which then kills the plugin without any output. With
+--deadline MS, the plugin stops sending probes after MS milliseconds and
+evaluates the samples collected so far, as long as there are at least 2.
+Otherwise the check returns UNKNOWN. Probes still waiting for a reply at the
+deadline are left out rather than counted as lost.
+
+With --all-addresses, the deadline covers all addresses. The time left is split
+evenly between the addresses not yet measured, so time that one address does
+not use is shared by the addresses after it. An address that gets fewer than 2
+samples in its share is UNKNOWN, and is combined with the other addresses as
+described for --overall-state.
+
+TCP MODE
+
+Targets that drop ICMP can be measured with --tcp PORT, which times the TCP
+three-way handshake to the given port instead of sending pings. The connections
+are closed right after the handshake. Handshakes that time out are counted as
+lost, while refused connections make the check return UNKNOWN. TCP mode does not
+require any elevated permissions.
+
+UDP MODE
+
+With --udp PORT, the plugin sends UDP datagrams to an echo service (RFC 862) on
+the given port. Each datagram carries a sequence number, so late or reordered
+replies are not mistaken for the reply to the current datagram. Datagrams that
+are not echoed within the timeout are counted as lost.
+
+The plugin can act as the echo service on the far end with --responder, which
+listens on the --udp port of the --host address (0.0.0.0 if not given):
+
+  check_jitter --responder --udp 7777
+
+TWAMP-LIGHT MODE
+
+With --twamp PORT, the plugin acts as a TWAMP-Light session sender (RFC 5357,
+appendix I) towards a session reflector on the given port. The reflector
+timestamps each test packet when it is received and when it is sent back, which
+splits the round trip into a forward and a reverse delay. The jitter of each
+direction is reported as its own performance data label, e.g. forward_avg_jitter,
+and the round trip time excludes the time spent in the reflector.
+
+The clocks of the two hosts do not need to be synchronized, since only the
+variation of each one-way delay is reported, but they should not drift during
+the check.
+
+The plugin can act as the session reflector with --responder:
+
+  check_jitter --responder --twamp 862
+
+HTTP MODE
+
+With --http URL, the plugin sends HTTP GET requests to the URL instead of
+sending pings, using a new connection for every request. Each request is split
+into the time to resolve the hostname (DNS), to connect (Connect), to complete
+the TLS handshake for https URLs (TLS) and to receive the first byte of the
+response (TTFB). The jitter of each step is reported as its own performance data
+label, e.g. ttfb_avg_jitter, and the round trip time is the sum of the steps.
+
+A response with a status code other than 2xx or 3xx, e.g. 404 or 500, makes
+the check UNKNOWN. Redirects are not followed. HTTPS certificates are verified
+against the certificate store of the operating system.
+
+  check_jitter --http https://example.com/health --threshold metric=ttfb_p95_jitter,crit=50
+
+DNS MODE
+
+With --dns NAME, the plugin sends DNS queries for NAME over UDP to the server
+given with --host and measures the time until each response. The record type is
+set with --dns-type (A by default) and the port with --dns-port (53 by default).
+The root name is given as ".", e.g. --dns . --dns-type NS.
+Every query uses a new random ID, so late responses are not mistaken for the
+response to the current query. Any response counts as received, whatever its
+response code, while queries without a response are counted as lost.
+
+  check_jitter -H 192.0.2.53 --dns example.com --dns-type AAAA -w 5 -c 10
+
+ICMP TIMESTAMP MODE
+
+With --icmp-timestamp, the plugin sends ICMP timestamp requests (type 13)
+instead of echo requests. The target records when each request was received and
+when the reply was sent, which splits the round trip into a forward and a
+reverse delay, reported the same way as in TWAMP-Light mode. The clocks of the
+two hosts do not need to be synchronized.
+
+The timestamps have a resolution of one millisecond, so this mode is best suited
+for paths with several milliseconds of jitter. ICMP timestamps only exist for
+IPv4, are often filtered, and require a raw socket.
+
 SAMPLES
 
 The number of pings to send to the target host. Must be greater than 2.
@@ -63,26 +258,114 @@ Example ranges:
 | 10:20            | < 10 or > 20, (outside the range of {10 .. 20}) |
 +------------------+-------------------------------------------------+
 | @10:20           | ≥ 10 and ≤ 20, (inside the range of {10 .. 20}) |
-+------------------+-------------------------------------------------+"#;
++------------------+-------------------------------------------------+
+
+PER-METRIC THRESHOLDS
+
+Thresholds for any reported metric can be given with --threshold, which may be
+repeated:
+
+  --threshold metric=avg_jitter,warn=5,crit=10 --threshold metric=loss,crit=5
+
+Metric names are <method>_jitter for each aggregation method (e.g. avg_jitter,
+p95_jitter, rfc3550_jitter), loss, rtt_min, rtt_avg, rtt_max and rtt_mdev. In
+TWAMP-Light and ICMP timestamp mode, forward_<method>_jitter and
+reverse_<method>_jitter are also reported, and in HTTP mode dns_<method>_jitter,
+connect_<method>_jitter, tls_<method>_jitter and ttfb_<method>_jitter.
+In ICMP echo, UDP and TWAMP-Light mode, reordered and duplicates count the
+replies that arrived after the reply to a later probe (RFC 4737) and the extra
+copies of replies, e.g. --threshold metric=reordered,warn=0 to warn about any.
+Thresholds given with --threshold override the ones given with other options.
+The aggregation method of a jitter metric must also be given with -a."#;
 
 #[derive(Parser, Debug)]
 #[command(author, version, long_about = None, about = ABOUT_TEXT)]
+#[command(group(ArgGroup::new("responder_mode").args(["udp", "twamp"])))]
 struct Args {
-    /// Aggregation method to use for multiple samples
-    #[arg(short, long, default_value = "average")]
-    aggregation_method: AggregationMethod,
+    /// Measure every address the host resolves to instead of only the first
+    #[arg(long, conflicts_with_all = ["http", "responder"])]
+    all_addresses: bool,
+
+    /// Aggregation method to use for multiple samples, may be repeated or comma separated
+    #[arg(short, long, default_value = "average", value_delimiter = ',')]
+    aggregation_method: Vec<AggregationMethod>,
 
-    /// Critical limit for network jitter in milliseconds
-    #[arg(short, long)]
-    critical: Option<String>,
+    /// Critical limit for network jitter in milliseconds, one per aggregation method or one for all
+    #[arg(short, long, value_delimiter = ',')]
+    critical: Vec<String>,
+
+    /// Overall time limit in milliseconds, after which the samples collected so far are evaluated.
+    /// Shared evenly between the addresses with --all-addresses
+    #[arg(long, value_parser = value_parser!(u64).range(1..), conflicts_with = "responder")]
+    deadline: Option<u64>,
 
     /// Use a datagram socket instead of a raw socket (expert option)
     #[arg(long, short = 'D')]
     dgram_socket: bool,
 
-    /// Hostname or IP address to ping
-    #[arg(long, short = 'H')]
-    host: String,
+    /// Send DNS queries for this name to the --host server instead of sending ICMP pings
+    #[arg(long, conflicts_with_all = ["dgram_socket", "tcp", "udp", "twamp", "icmp_timestamp", "http", "responder"])]
+    dns: Option<String>,
+
+    /// Port of the DNS server to query with --dns
+    #[arg(long, default_value = "53", requires = "dns")]
+    dns_port: u16,
+
+    /// Record type to query for with --dns, e.g. A, AAAA, MX or TYPE65
+    #[arg(long, default_value = "A", requires = "dns")]
+    dns_type: RecordType,
+
+    /// Set the Don't Fragment bit on the probes, so that probes larger than the path MTU fail (Linux only)
+    #[arg(long, conflicts_with = "responder")]
+    dont_fragment: bool,
+
+    /// DSCP marking of the probes, by name (e.g. EF, AF41, CS3) or value (0-63)
+    #[arg(long, conflicts_with = "responder")]
+    dscp: Option<Dscp>,
+
+    /// Send an ICMP ping every this many milliseconds, without waiting for the previous reply
+    #[arg(long, value_parser = value_parser!(u64).range(1..), conflicts_with_all = ["tcp", "udp", "twamp", "http", "dns", "icmp_timestamp", "responder", "kernel_timestamps", "min_interval", "max_interval"])]
+    fixed_rate: Option<u64>,
+
+    /// Hostname or IP address to ping, or the address to listen on with --responder
+    #[arg(long, short = 'H', required_unless_present_any = ["responder", "http"])]
+    host: Option<String>,
+
+    /// Send HTTP(S) GET requests to this URL instead of sending ICMP pings
+    #[arg(long, conflicts_with_all = ["host", "dgram_socket", "tcp", "udp", "twamp", "icmp_timestamp", "responder"])]
+    http: Option<url::Url>,
+
+    /// Send ICMP timestamp requests to measure forward and reverse jitter (IPv4 only)
+    #[arg(long, conflicts_with_all = ["dgram_socket", "tcp", "udp", "twamp"])]
+    icmp_timestamp: bool,
+
+    /// Network interface to send the probes through, e.g. eth1 (Linux only)
+    #[arg(long, short = 'I', conflicts_with = "responder")]
+    interface: Option<String>,
+
+    /// Only use IPv4 addresses of the host
+    #[arg(short = '4', long, conflicts_with_all = ["ipv6", "responder"])]
+    ipv4: bool,
+
+    /// Only use IPv6 addresses of the host
+    #[arg(short = '6', long, conflicts_with = "responder")]
+    ipv6: bool,
+
+    /// Take the send and receive times of ICMP pings from the kernel instead of user space (Linux only)
+    #[arg(long, conflicts_with_all = ["tcp", "udp", "twamp", "http", "dns", "icmp_timestamp", "responder", "fixed_rate"])]
+    kernel_timestamps: bool,
+
+    /// Critical limit for packet loss in percent
+    #[arg(long)]
+    loss_critical: Option<String>,
+
+    /// Warning limit for packet loss in percent
+    #[arg(long)]
+    loss_warning: Option<String>,
+
+    /// Maximum packet loss in percent before the check returns UNKNOWN
+    #[arg(long, default_value = "100", value_parser = parse_percentage)]
+    max_loss: f64,
 
     /// Minimum interval between ping samples in milliseconds
     #[arg(short, long, default_value = "0")]
@@ -92,32 +375,186 @@ struct Args {
     #[arg(short, long, default_value = "0", short = 'M')]
     max_interval: u64,
 
+    /// How to combine the states of the addresses with --all-addresses: worst or best
+    #[arg(long, default_value = "worst", requires = "all_addresses")]
+    overall_state: OverallState,
+
+    /// Pattern of 1-16 bytes in hex to fill the probe payload with instead of random bytes, e.g. ff00
+    #[arg(long, conflicts_with_all = ["tcp", "http", "dns", "icmp_timestamp", "responder"])]
+    payload_pattern: Option<PayloadPattern>,
+
+    /// Payload size of the probes in bytes (ICMP echo, UDP and TWAMP-Light modes)
+    #[arg(long, value_parser = value_parser!(u16).range(..=65_507), conflicts_with_all = ["tcp", "http", "dns", "icmp_timestamp", "responder"])]
+    payload_size: Option<u16>,
+
     /// Precision of the output decimal places
     #[arg(short, long, default_value = "3")]
     precision: u8,
 
+    /// Run a UDP echo responder or TWAMP-Light reflector on the --udp or --twamp port instead of checking jitter
+    #[arg(long, requires = "responder_mode")]
+    responder: bool,
+
+    /// Critical limit for the average round trip time in milliseconds
+    #[arg(long)]
+    rtt_critical: Option<String>,
+
+    /// Warning limit for the average round trip time in milliseconds
+    #[arg(long)]
+    rtt_warning: Option<String>,
+
     /// Sample size: the number of pings to send
     #[arg(short, long, default_value = "10", value_parser = value_parser!(u8).range(3..))]
     samples: u8,
 
+    /// Local address to send the probes from
+    #[arg(long, short = 'S', conflicts_with = "responder")]
+    source: Option<IpAddr>,
+
+    /// Thresholds for a named metric, e.g. metric=p95_jitter,warn=5,crit=10 (may be repeated)
+    #[arg(short = 'T', long, value_parser = parse_metric_threshold)]
+    threshold: Vec<(Metric, Thresholds)>,
+
+    /// Measure the time of TCP handshakes to this port instead of sending ICMP pings
+    #[arg(long, conflicts_with = "dgram_socket")]
+    tcp: Option<u16>,
+
+    /// Send UDP datagrams to an echo service on this port instead of sending ICMP pings
+    #[arg(long, conflicts_with_all = ["dgram_socket", "tcp"])]
+    udp: Option<u16>,
+
+    /// Send TWAMP-Light test packets to a session reflector on this port instead of sending ICMP pings
+    #[arg(long, conflicts_with_all = ["dgram_socket", "tcp", "udp"])]
+    twamp: Option<u16>,
+
     /// Timeout in milliseconds per individual ping check
     #[arg(short, long, default_value = "1000")]
     timeout: u64,
 
-    /// Warning limit for network jitter in milliseconds
-    #[arg(short, long)]
-    warning: Option<String>,
+    /// Time to live (IPv4) or hop limit (IPv6) of the probes
+    #[arg(long, value_parser = value_parser!(u32).range(1..=255), conflicts_with = "responder")]
+    ttl: Option<u32>,
+
+    /// Warning limit for network jitter in milliseconds, one per aggregation method or one for all
+    #[arg(short, long, value_delimiter = ',')]
+    warning: Vec<String>,
 
     /// Enable verbose output. Use multiple times to increase verbosity (e.g. -vvv)
     #[arg(short, long, action = Count, value_parser = value_parser!(u8).range(0..=3))]
     verbose: u8,
 }
 
+fn parse_percentage(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(p) if (0.0..=100.0).contains(&p) => Ok(p),
+        _ => Err(format!("'{}' is not a percentage between 0 and 100", s)),
+    }
+}
+
+fn parse_threshold(s: Option<String>) -> Option<ThresholdRange> {
+    match s {
+        Some(s) => ThresholdRange::from(s.as_str())
+            .map_err(|e| exit_with_message(Status::Unknown(UnknownVariant::RangeParseError(s, e))))
+            .ok(),
+        None => None,
+    }
+}
+
+/// Match the given ranges to the aggregation methods, applying a single range to all of them.
+fn parse_jitter_thresholds(
+    name: &str,
+    ranges: Vec<String>,
+    count: usize,
+) -> Vec<Option<ThresholdRange>> {
+    match ranges.len() {
+        0 => vec![None; count],
+        1 => vec![parse_threshold(ranges.into_iter().next()); count],
+        n if n == count => ranges
+            .into_iter()
+            .map(|r| parse_threshold(Some(r)))
+            .collect(),
+        n => exit_with_message(Status::Unknown(UnknownVariant::MismatchedThresholds(
+            name.to_string(),
+            n,
+            count,
+        ))),
+    }
+}
+
+fn insert_thresholds(
+    thresholds: &mut MetricThresholds,
+    metric: Metric,
+    warning: Option<ThresholdRange>,
+    critical: Option<ThresholdRange>,
+) {
+    if warning.is_some() || critical.is_some() {
+        thresholds.insert(metric, Thresholds { warning, critical });
+    }
+}
+
 fn exit_with_message(status: Status) -> ! {
     println!("{}", status);
     process::exit(status.to_int());
 }
 
+/// The reason a measurement failed, where running out of time before enough samples were
+/// collected is reported as a timeout.
+fn error_variant(e: CheckJitterError, deadline: Option<u64>) -> UnknownVariant {
+    let deadline_exceeded = match &e {
+        CheckJitterError::DeadlineExceeded(_) => true,
+        CheckJitterError::AddressFailed(_, e) => {
+            matches!(**e, CheckJitterError::DeadlineExceeded(_))
+        }
+        _ => false,
+    };
+    match deadline {
+        Some(d) if deadline_exceeded => {
+            info!("{}", e);
+            UnknownVariant::Timeout(Duration::from_millis(d))
+        }
+        _ => UnknownVariant::Error(e),
+    }
+}
+
+/// The values of a measurement to evaluate, or the reason it cannot be evaluated.
+fn measurement_perfdata(
+    measurement: Measurement,
+    address: Option<IpAddr>,
+    thresholds: &MetricThresholds,
+    max_loss: f64,
+    precision: u8,
+    dscp: Option<Dscp>,
+) -> Result<Vec<Perfdata>, UnknownVariant> {
+    let packet_loss = round_jitter(measurement.packet_loss, precision);
+    if packet_loss > max_loss {
+        return Err(UnknownVariant::MaxLossExceeded(packet_loss, max_loss));
+    }
+
+    let metrics = measurement.metrics();
+    if let Some((metric, _)) = thresholds
+        .iter()
+        .find(|(m, _)| !metrics.iter().any(|(measured, _)| measured == m))
+    {
+        return Err(UnknownVariant::UnmeasuredMetric(*metric));
+    }
+
+    let mut perfdata = Vec::new();
+    for (metric, value) in metrics {
+        let Some(value) = value else {
+            info!("{} left out, too few replies were received", metric);
+            continue;
+        };
+        perfdata.push(Perfdata {
+            metric,
+            value: round_jitter(value, precision),
+            thresholds: thresholds.get(&metric),
+            address,
+            dscp,
+        });
+    }
+    Ok(perfdata)
+}
+
 fn validate_host(s: &str) -> Result<String, CheckJitterError> {
     if s.parse::<Ipv4Addr>().is_ok() {
         return Ok(s.to_string());
@@ -133,6 +570,29 @@ fn validate_host(s: &str) -> Result<String, CheckJitterError> {
     }
 }
 
+fn run_responder(host: Option<&str>, socket_type: SocketType) -> ! {
+    let ip = match host {
+        Some(h) => match h.parse::<IpAddr>() {
+            Ok(ip) => ip,
+            Err(_) => {
+                exit_with_message(Status::Unknown(UnknownVariant::InvalidAddr(h.to_string())))
+            }
+        },
+        None => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+    };
+
+    let result = match socket_type {
+        SocketType::Twamp(port) => run_twamp_reflector(SocketAddr::new(ip, port)),
+        SocketType::Udp(port) => run_udp_responder(SocketAddr::new(ip, port)),
+        _ => unreachable!("clap requires --udp or --twamp with --responder"),
+    };
+
+    match result {
+        Ok(()) => process::exit(0),
+        Err(e) => exit_with_message(Status::Unknown(UnknownVariant::Error(e))),
+    }
+}
+
 fn select_and_init_logger(verbosity: u8) -> Result<(), fern::InitError> {
     setup_logger(match verbosity {
         3 => (LevelFilter::Debug, true),
@@ -174,6 +634,8 @@ fn setup_logger((level, include_file_info): (LevelFilter, bool)) -> Result<(), f
 
 /// Check network jitter.
 fn main() {
+    let start_time = Instant::now();
+
     // According to monitoring-plugins guidelines, exit code 3 is used for "UNKNOWN" and
     // should be used for the --help and --version flags.
     let args = Args::try_parse().unwrap_or_else(|e| match e.kind() {
@@ -190,6 +652,45 @@ fn main() {
         )))
     }
 
+    let socket_type = if let Some(url) = &args.http {
+        SocketType::Http(url.clone())
+    } else if let Some(name) = &args.dns {
+        SocketType::Dns {
+            port: args.dns_port,
+            name: name.clone(),
+            record_type: args.dns_type,
+        }
+    } else if let Some(port) = args.tcp {
+        SocketType::Tcp(port)
+    } else if let Some(port) = args.udp {
+        SocketType::Udp(port)
+    } else if let Some(port) = args.twamp {
+        SocketType::Twamp(port)
+    } else if args.icmp_timestamp {
+        SocketType::Timestamp
+    } else if args.dgram_socket {
+        SocketType::Datagram
+    } else {
+        SocketType::Raw
+    };
+
+    if args.responder {
+        run_responder(args.host.as_deref(), socket_type)
+    }
+
+    // Clap requires --host unless --responder or --http is given.
+    let host = match &args.http {
+        Some(url) => match url.host() {
+            Some(url::Host::Domain(domain)) => domain.to_string(),
+            Some(url::Host::Ipv4(ip)) => ip.to_string(),
+            Some(url::Host::Ipv6(ip)) => ip.to_string(),
+            None => exit_with_message(Status::Unknown(UnknownVariant::Error(
+                CheckJitterError::UnsupportedUrl(url.to_string()),
+            ))),
+        },
+        None => args.host.unwrap_or_default(),
+    };
+
     if args.min_interval > args.max_interval {
         exit_with_message(Status::Unknown(UnknownVariant::InvalidMinMaxInterval(
             args.min_interval,
@@ -197,72 +698,202 @@ fn main() {
         )))
     }
 
-    if validate_host(&args.host).is_err() {
-        exit_with_message(Status::Unknown(UnknownVariant::InvalidAddr(
-            args.host.clone(),
-        )))
+    if validate_host(&host).is_err() {
+        exit_with_message(Status::Unknown(UnknownVariant::InvalidAddr(host)))
     }
 
-    if args.warning.is_none() && args.critical.is_none() {
+    let method_count = args.aggregation_method.len();
+    let warnings = parse_jitter_thresholds("warning", args.warning, method_count);
+    let criticals = parse_jitter_thresholds("critical", args.critical, method_count);
+
+    let mut thresholds = MetricThresholds::default();
+    for (method, (warning, critical)) in args
+        .aggregation_method
+        .iter()
+        .zip(warnings.into_iter().zip(criticals))
+    {
+        insert_thresholds(&mut thresholds, Metric::Jitter(*method), warning, critical);
+    }
+    insert_thresholds(
+        &mut thresholds,
+        Metric::PacketLoss,
+        parse_threshold(args.loss_warning),
+        parse_threshold(args.loss_critical),
+    );
+    insert_thresholds(
+        &mut thresholds,
+        Metric::RttAvg,
+        parse_threshold(args.rtt_warning),
+        parse_threshold(args.rtt_critical),
+    );
+    // Thresholds given with --threshold take precedence over the ones given with other options.
+    for (metric, t) in args.threshold {
+        thresholds.insert(metric, t);
+    }
+
+    if thresholds.is_empty() {
         exit_with_message(Status::Unknown(UnknownVariant::NoThresholds))
     }
 
-    let warning: Option<ThresholdRange> = match args.warning {
-        Some(w) => ThresholdRange::from(w.as_str())
-            .map_err(|e| exit_with_message(Status::Unknown(UnknownVariant::RangeParseError(w, e))))
-            .ok(),
-        None => None,
-    };
+    let timeout = Duration::from_millis(args.timeout);
 
-    let critical: Option<ThresholdRange> = match args.critical {
-        Some(c) => ThresholdRange::from(c.as_str())
-            .map_err(|e| exit_with_message(Status::Unknown(UnknownVariant::RangeParseError(c, e))))
-            .ok(),
-        None => None,
+    let options = SocketOptions {
+        source: args.source,
+        interface: args.interface,
+        dscp: args.dscp,
+        payload_size: args.payload_size.map(usize::from),
+        payload_pattern: args.payload_pattern,
+        dont_fragment: args.dont_fragment,
+        ttl: args.ttl,
+        kernel_timestamps: args.kernel_timestamps,
+        fixed_rate: args.fixed_rate.map(Duration::from_millis),
+        deadline: args.deadline.map(|d| start_time + Duration::from_millis(d)),
     };
 
-    let thresholds = Thresholds { warning, critical };
-    let timeout = Duration::from_millis(args.timeout);
-
-    let socket_type = if args.dgram_socket {
-        SocketType::Datagram
+    let family = if args.ipv4 {
+        AddressFamily::Ipv4
+    } else if args.ipv6 {
+        AddressFamily::Ipv6
     } else {
-        SocketType::Raw
+        AddressFamily::Any
     };
 
-    info!("{:<34}{}", "Will check jitter for host:", args.host);
-    info!("{:<34}{}", "Aggregation method:", args.aggregation_method);
+    info!("{:<34}{}", "Will check jitter for host:", host);
+    info!(
+        "{:<34}{}",
+        "Aggregation method:",
+        args.aggregation_method
+            .iter()
+            .map(|m| m.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    );
+    info!("{:<34}{}", "Address family:", family);
     info!("{:<34}{}", "Socket type:", socket_type);
+    if let Some(source) = &options.source {
+        info!("{:<34}{}", "Source address:", source);
+    }
+    if let Some(interface) = &options.interface {
+        info!("{:<34}{}", "Interface:", interface);
+    }
+    if let Some(dscp) = &options.dscp {
+        info!("{:<34}{}", "DSCP marking:", dscp);
+    }
+    if let Some(size) = &options.payload_size {
+        info!("{:<34}{} bytes", "Payload size:", size);
+    }
+    if let Some(pattern) = &options.payload_pattern {
+        info!("{:<34}{}", "Payload pattern:", pattern);
+    }
+    if options.dont_fragment {
+        info!("{:<34}{}", "Don't fragment:", options.dont_fragment);
+    }
+    if let Some(ttl) = &options.ttl {
+        info!("{:<34}{}", "Time to live:", ttl);
+    }
+    if options.kernel_timestamps {
+        info!("{:<34}{}", "Kernel timestamps:", options.kernel_timestamps);
+    }
+    if args.all_addresses {
+        info!(
+            "{:<34}{}",
+            "Overall state of addresses:", args.overall_state
+        );
+    }
     info!("{:<34}{}", "Sample size:", args.samples);
     info!("{:<34}{}ms", "Timeout per ping:", args.timeout);
-    info!(
-        "{:<34}{}ms",
-        "Minimum wait time between pings:", args.min_interval
-    );
-    info!(
-        "{:<34}{}ms",
-        "Maximum wait time between pings:", args.max_interval
-    );
+    if let Some(deadline) = args.deadline {
+        info!("{:<34}{}ms", "Deadline:", deadline);
+    }
+    match args.fixed_rate {
+        Some(rate) => info!("{:<34}{}ms", "Fixed rate of pings:", rate),
+        None => {
+            info!(
+                "{:<34}{}ms",
+                "Minimum wait time between pings:", args.min_interval
+            );
+            info!(
+                "{:<34}{}ms",
+                "Maximum wait time between pings:", args.max_interval
+            );
+        }
+    }
+    info!("{:<34}{}%", "Maximum packet loss:", args.max_loss);
     info!("{:<34}{}", "Decimal precision:", args.precision);
-    info!("{:<34}{:?}", "Warning threshold:", warning);
-    info!("{:<34}{:?}", "Critical threshold:", critical);
-
-    let raw_jitter = match get_jitter(
-        args.aggregation_method,
-        &args.host,
-        socket_type,
-        args.samples,
-        timeout,
-        args.min_interval,
-        args.max_interval,
-    ) {
-        Ok(jitter) => jitter,
-        Err(e) => exit_with_message(Status::Unknown(UnknownVariant::Error(e))),
-    };
+    for (metric, t) in thresholds.iter() {
+        info!(
+            "{:<34}{:?}",
+            format!("{} warning threshold:", metric),
+            t.warning
+        );
+        info!(
+            "{:<34}{:?}",
+            format!("{} critical threshold:", metric),
+            t.critical
+        );
+    }
 
-    exit_with_message(evaluate_thresholds(
-        args.aggregation_method,
-        round_jitter(raw_jitter, args.precision),
-        &thresholds,
-    ))
+    if args.all_addresses {
+        let measurements = match get_jitter_per_address(
+            &args.aggregation_method,
+            &host,
+            family,
+            socket_type,
+            &options,
+            args.samples,
+            timeout,
+            args.min_interval,
+            args.max_interval,
+        ) {
+            Ok(measurements) => measurements,
+            Err(e) => exit_with_message(Status::Unknown(error_variant(e, args.deadline))),
+        };
+        let addresses = measurements
+            .into_iter()
+            .map(|(ip, measurement)| {
+                let perfdata = match measurement {
+                    Ok(m) => measurement_perfdata(
+                        m,
+                        Some(ip),
+                        &thresholds,
+                        args.max_loss,
+                        args.precision,
+                        options.dscp,
+                    ),
+                    Err(e) => Err(error_variant(
+                        CheckJitterError::AddressFailed(ip, Box::new(e)),
+                        args.deadline,
+                    )),
+                };
+                (ip, perfdata)
+            })
+            .collect();
+        exit_with_message(evaluate_addresses(addresses, args.overall_state))
+    } else {
+        let measurement = match get_jitter(
+            &args.aggregation_method,
+            &host,
+            family,
+            socket_type,
+            &options,
+            args.samples,
+            timeout,
+            args.min_interval,
+            args.max_interval,
+        ) {
+            Ok(measurement) => measurement,
+            Err(e) => exit_with_message(Status::Unknown(error_variant(e, args.deadline))),
+        };
+        match measurement_perfdata(
+            measurement,
+            None,
+            &thresholds,
+            args.max_loss,
+            args.precision,
+            options.dscp,
+        ) {
+            Ok(perfdata) => exit_with_message(evaluate_thresholds(perfdata)),
+            Err(e) => exit_with_message(Status::Unknown(e)),
+        }
+    }
 }
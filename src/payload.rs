@@ -0,0 +1,105 @@
+use crate::{CheckJitterError, SocketOptions};
+use rand::RngCore;
+use std::fmt;
+use std::str::FromStr;
+
+/// The longest pattern accepted, the same as for `ping -p`.
+const MAX_PATTERN_LEN: usize = 16;
+
+/// A sequence of bytes repeated to fill the payload of the probes, given in hex, e.g. `ff00`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PayloadPattern(Vec<u8>);
+
+impl FromStr for PayloadPattern {
+    type Err = String;
+
+    /// Parse a pattern of 1 to 16 bytes given as hex digits, optionally prefixed with `0x`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("'{}' is not a valid pattern of 1-16 bytes in hex", s);
+        let hex = s.strip_prefix("0x").unwrap_or(s);
+        if hex.is_empty() || hex.len() % 2 != 0 || hex.len() > MAX_PATTERN_LEN * 2 {
+            return Err(error());
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>()
+            .map(PayloadPattern)
+            .ok_or_else(error)
+    }
+}
+
+impl fmt::Display for PayloadPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Fill `buf` with the repeated pattern, or with random bytes if there is none.
+pub(crate) fn fill(buf: &mut [u8], pattern: Option<&PayloadPattern>) {
+    match pattern {
+        Some(PayloadPattern(bytes)) => {
+            for (b, p) in buf.iter_mut().zip(bytes.iter().cycle()) {
+                *b = *p;
+            }
+        }
+        None => rand::thread_rng().fill_bytes(buf),
+    }
+}
+
+/// The payload size of the probes of a mode, which needs at least `min` bytes for its own
+/// header and sends `default` bytes unless another size is set in `options`.
+pub(crate) fn payload_size(
+    options: &SocketOptions,
+    min: usize,
+    default: usize,
+) -> Result<usize, CheckJitterError> {
+    match options.payload_size {
+        Some(size) if size < min => Err(CheckJitterError::PayloadTooSmall(size, min)),
+        Some(size) => Ok(size),
+        None => Ok(default),
+    }
+}
+
+#[cfg(test)]
+mod payload_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_pattern() {
+        assert_eq!("ff00".parse(), Ok(PayloadPattern(vec![0xff, 0x00])));
+        assert_eq!("0xAB".parse(), Ok(PayloadPattern(vec![0xab])));
+        assert!("f".parse::<PayloadPattern>().is_err());
+        assert!("zz".parse::<PayloadPattern>().is_err());
+        assert!("".parse::<PayloadPattern>().is_err());
+        assert!("00".repeat(17).parse::<PayloadPattern>().is_err());
+        assert_eq!(PayloadPattern(vec![0xde, 0xad]).to_string(), "dead");
+    }
+
+    #[test]
+    fn test_fill_with_pattern() {
+        let mut buf = [0; 5];
+        fill(&mut buf, Some(&PayloadPattern(vec![1, 2])));
+
+        assert_eq!(buf, [1, 2, 1, 2, 1]);
+    }
+
+    #[test]
+    fn test_payload_size() {
+        let options = SocketOptions {
+            payload_size: Some(4),
+            ..Default::default()
+        };
+
+        assert_eq!(payload_size(&SocketOptions::default(), 8, 24), Ok(24));
+        assert_eq!(payload_size(&options, 0, 24), Ok(4));
+        assert_eq!(
+            payload_size(&options, 8, 24),
+            Err(CheckJitterError::PayloadTooSmall(4, 8))
+        );
+    }
+}
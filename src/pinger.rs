@@ -0,0 +1,140 @@
+use crate::{CheckJitterError, Reply, SequenceErrors};
+#[cfg(test)]
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// A way of probing a target, driven once per sample by the measurement.
+///
+/// Implemented by every probe type of the plugin, e.g. [`IcmpEcho`](crate::IcmpEcho), and by a
+/// scripted mock in the tests, which drive the measurement without root or a network.
+pub trait Pinger {
+    /// Send a single probe and wait up to `timeout` for its reply.
+    ///
+    /// Returns `None` if the probe was lost, which counts towards the packet loss. Errors abort
+    /// the measurement.
+    fn probe(&mut self, timeout: Duration) -> Result<Option<Reply>, CheckJitterError>;
+
+    /// Send `samples` probes every `interval` without waiting for the replies in between, and
+    /// match the replies to the probes as they arrive, waiting up to `timeout` for each.
+    ///
+    /// No probe is sent or waited for beyond the `deadline`, and the probes still waiting for
+    /// their reply when it passes are left out rather than counted as lost.
+    ///
+    /// Probe types that cannot have several probes in flight return
+    /// `CheckJitterError::FixedRateUnsupported`, which the default implementation does.
+    fn probe_at_rate(
+        &mut self,
+        samples: u8,
+        interval: Duration,
+        timeout: Duration,
+        deadline: Option<Instant>,
+    ) -> Result<Vec<Option<Reply>>, CheckJitterError> {
+        let _ = (samples, interval, timeout, deadline);
+        Err(CheckJitterError::FixedRateUnsupported)
+    }
+
+    /// The replies received so far that arrived out of order or more than once.
+    ///
+    /// Probe types that do not number their probes cannot tell, and return `None`, which the
+    /// default implementation does.
+    fn sequence_errors(&self) -> Option<SequenceErrors> {
+        None
+    }
+}
+
+/// A deterministic pinger that replays a script of replies, losses and errors.
+///
+/// Probes beyond the end of the script are lost.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub(crate) struct MockPinger {
+    script: VecDeque<Result<Option<Reply>, CheckJitterError>>,
+    probes: usize,
+}
+
+#[cfg(test)]
+impl MockPinger {
+    /// Replay the given outcomes in order, one per probe.
+    pub(crate) fn new(script: Vec<Result<Option<Reply>, CheckJitterError>>) -> Self {
+        MockPinger {
+            script: script.into(),
+            probes: 0,
+        }
+    }
+
+    /// Replay replies with the given round trip times in milliseconds, where `None` is a lost
+    /// probe.
+    pub(crate) fn from_rtts(rtts: &[Option<u64>]) -> Self {
+        Self::new(
+            rtts.iter()
+                .map(|rtt| Ok(rtt.map(|ms| Reply::from(Duration::from_millis(ms)))))
+                .collect(),
+        )
+    }
+
+    /// The number of probes sent so far.
+    pub(crate) fn probes(&self) -> usize {
+        self.probes
+    }
+}
+
+#[cfg(test)]
+impl Pinger for MockPinger {
+    fn probe(&mut self, _timeout: Duration) -> Result<Option<Reply>, CheckJitterError> {
+        self.probes += 1;
+        self.script.pop_front().unwrap_or(Ok(None))
+    }
+}
+
+#[cfg(test)]
+mod mock_pinger_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_from_rtts() {
+        let mut pinger = MockPinger::from_rtts(&[Some(10), None]);
+        let timeout = Duration::from_secs(1);
+
+        assert_eq!(
+            pinger.probe(timeout).unwrap().map(|r| r.rtt),
+            Some(Duration::from_millis(10))
+        );
+        assert_eq!(pinger.probe(timeout), Ok(None));
+        assert_eq!(pinger.probes(), 2);
+    }
+
+    #[test]
+    fn test_replays_script() {
+        let mut pinger = MockPinger::new(vec![
+            Ok(Some(Reply::from(Duration::from_millis(5)))),
+            Ok(None),
+            Err(CheckJitterError::PermissionDenied),
+        ]);
+        let timeout = Duration::from_secs(1);
+
+        assert_eq!(
+            pinger.probe(timeout),
+            Ok(Some(Reply::from(Duration::from_millis(5))))
+        );
+        assert_eq!(pinger.probe(timeout), Ok(None));
+        assert_eq!(
+            pinger.probe(timeout),
+            Err(CheckJitterError::PermissionDenied)
+        );
+        assert_eq!(pinger.probe(timeout), Ok(None));
+        assert_eq!(pinger.probes(), 4);
+    }
+
+    #[test]
+    fn test_probe_at_rate_is_unsupported() {
+        let mut pinger = MockPinger::from_rtts(&[Some(5)]);
+        let interval = Duration::from_millis(20);
+
+        assert_eq!(
+            pinger.probe_at_rate(1, interval, Duration::from_secs(1), None),
+            Err(CheckJitterError::FixedRateUnsupported)
+        );
+        assert_eq!(pinger.probes(), 0);
+    }
+}
@@ -0,0 +1,104 @@
+/// Counts of replies that arrived out of order or more than once.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SequenceErrors {
+    /// Replies that arrived after the reply to a later probe, as defined by RFC 4737.
+    pub reordered: u32,
+    /// Copies of replies that had already arrived.
+    pub duplicates: u32,
+}
+
+/// Tracks the arrival order of the replies to a series of probes, which are numbered from 0 in
+/// the order they were sent.
+///
+/// Every reply to a probe that was sent is tracked, including replies that arrived too late to
+/// be measured.
+#[derive(Debug, Default)]
+pub(crate) struct SequenceTracker {
+    received: Vec<bool>,
+    /// The next probe expected to be replied to, one past the latest probe replied to so far.
+    next_expected: usize,
+    errors: SequenceErrors,
+}
+
+impl SequenceTracker {
+    /// Record that the next probe was sent.
+    pub(crate) fn sent(&mut self) {
+        self.received.push(false);
+    }
+
+    /// Record a reply to the probe at `index`. Replies to probes that were not sent are ignored.
+    pub(crate) fn received(&mut self, index: usize) {
+        match self.received.get_mut(index) {
+            Some(true) => self.errors.duplicates += 1,
+            Some(received) => {
+                *received = true;
+                if index < self.next_expected {
+                    self.errors.reordered += 1;
+                } else {
+                    self.next_expected = index + 1;
+                }
+            }
+            None => (),
+        }
+    }
+
+    pub(crate) fn errors(&self) -> SequenceErrors {
+        self.errors
+    }
+}
+
+#[cfg(test)]
+mod sequence_tracker_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn track(sent: usize, arrivals: &[usize]) -> SequenceErrors {
+        let mut tracker = SequenceTracker::default();
+        for _ in 0..sent {
+            tracker.sent();
+        }
+        for index in arrivals {
+            tracker.received(*index);
+        }
+        tracker.errors()
+    }
+
+    #[test]
+    fn test_in_order() {
+        assert_eq!(track(4, &[0, 1, 3]), SequenceErrors::default());
+    }
+
+    #[test]
+    fn test_reordered() {
+        let expected = SequenceErrors {
+            reordered: 1,
+            duplicates: 0,
+        };
+
+        // Only the reply to probe 2 arrives after the reply to a later probe.
+        assert_eq!(track(8, &[0, 1, 3, 4, 2, 5, 6, 7]), expected);
+        // Every reply arrives after the reply to probe 3.
+        assert_eq!(
+            track(4, &[3, 0, 1, 2]),
+            SequenceErrors {
+                reordered: 3,
+                duplicates: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let expected = SequenceErrors {
+            reordered: 1,
+            duplicates: 2,
+        };
+
+        assert_eq!(track(3, &[0, 0, 2, 1, 2]), expected);
+    }
+
+    #[test]
+    fn test_not_sent() {
+        assert_eq!(track(2, &[0, 5, 1]), SequenceErrors::default());
+    }
+}
@@ -0,0 +1,382 @@
+use crate::{CheckJitterError, SocketOptions};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+
+#[cfg(unix)]
+const EMSGSIZE: i32 = libc::EMSGSIZE;
+#[cfg(windows)]
+const EMSGSIZE: i32 = 10040;
+
+const CODE_POINTS: [(&str, u8); 23] = [
+    ("CS0", 0),
+    ("LE", 1),
+    ("CS1", 8),
+    ("AF11", 10),
+    ("AF12", 12),
+    ("AF13", 14),
+    ("CS2", 16),
+    ("AF21", 18),
+    ("AF22", 20),
+    ("AF23", 22),
+    ("CS3", 24),
+    ("AF31", 26),
+    ("AF32", 28),
+    ("AF33", 30),
+    ("CS4", 32),
+    ("AF41", 34),
+    ("AF42", 36),
+    ("AF43", 38),
+    ("CS5", 40),
+    ("VA", 44),
+    ("EF", 46),
+    ("CS6", 48),
+    ("CS7", 56),
+];
+
+/// A Differentiated Services code point (RFC 2474), e.g. `EF` or `AF41`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Dscp(u8);
+
+impl Dscp {
+    /// The value of the TOS byte (IPv4) or traffic class (IPv6) with this code point and no
+    /// ECN bits set.
+    fn traffic_class(&self) -> u32 {
+        u32::from(self.0) << 2
+    }
+}
+
+impl FromStr for Dscp {
+    type Err = String;
+
+    /// Parse a code point by name, e.g. `EF`, or by its value from 0 to 63, e.g. `46`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let upper = s.to_uppercase();
+        if let Some((_, value)) = CODE_POINTS.iter().find(|(name, _)| *name == upper) {
+            return Ok(Dscp(*value));
+        }
+        match s.parse() {
+            Ok(value) if value < 64 => Ok(Dscp(value)),
+            _ => Err(format!("'{}' is not a valid DSCP name or value (0-63)", s)),
+        }
+    }
+}
+
+impl fmt::Display for Dscp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match CODE_POINTS.iter().find(|(_, value)| *value == self.0) {
+            Some((name, _)) => write!(f, "{}", name),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+/// Create a socket of the same IP version as `target`, bound according to `options`.
+///
+/// Sockets without a source address are bound to the unspecified address if `bind_any` is set,
+/// which connectionless sockets need before they can receive anything.
+pub(crate) fn new_socket(
+    target: IpAddr,
+    ty: Type,
+    protocol: Protocol,
+    options: &SocketOptions,
+    bind_any: bool,
+) -> Result<Socket, CheckJitterError> {
+    let domain = match target {
+        IpAddr::V4(_) => Domain::IPV4,
+        IpAddr::V6(_) => Domain::IPV6,
+    };
+    let socket = Socket::new(domain, ty, Some(protocol))?;
+
+    if let Some(interface) = &options.interface {
+        bind_device(&socket, interface)?;
+    }
+
+    if let Some(dscp) = options.dscp {
+        set_dscp(&socket, target, dscp)?;
+    }
+
+    if options.dont_fragment {
+        set_dont_fragment(&socket, target)?;
+    }
+
+    if let Some(ttl) = options.ttl {
+        match target {
+            IpAddr::V4(_) => socket.set_ttl(ttl)?,
+            IpAddr::V6(_) => socket.set_unicast_hops_v6(ttl)?,
+        }
+    }
+
+    let source = match (options.source, target) {
+        (Some(source), _) if source.is_ipv4() != target.is_ipv4() => {
+            return Err(CheckJitterError::SourceAddressMismatch(source, target));
+        }
+        (Some(source), _) => source,
+        (None, _) if !bind_any => return Ok(socket),
+        (None, IpAddr::V4(_)) => Ipv4Addr::UNSPECIFIED.into(),
+        (None, IpAddr::V6(_)) => Ipv6Addr::UNSPECIFIED.into(),
+    };
+    socket
+        .bind(&SocketAddr::new(source, 0).into())
+        .map_err(|e| match e.kind() {
+            ErrorKind::AddrNotAvailable => CheckJitterError::SourceAddressNotLocal(source),
+            ErrorKind::PermissionDenied => CheckJitterError::BindNotPermitted(source.to_string()),
+            _ => e.into(),
+        })?;
+
+    Ok(socket)
+}
+
+#[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+fn bind_device(socket: &Socket, interface: &str) -> Result<(), CheckJitterError> {
+    // ENODEV is returned for interfaces that do not exist.
+    const ENODEV: i32 = 19;
+
+    socket
+        .bind_device(Some(interface.as_bytes()))
+        .map_err(|e| match e.kind() {
+            ErrorKind::PermissionDenied => {
+                CheckJitterError::BindNotPermitted(interface.to_string())
+            }
+            _ if e.raw_os_error() == Some(ENODEV) => {
+                CheckJitterError::UnknownInterface(interface.to_string())
+            }
+            _ => e.into(),
+        })
+}
+
+#[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+fn bind_device(_socket: &Socket, interface: &str) -> Result<(), CheckJitterError> {
+    Err(CheckJitterError::InterfaceBindUnsupported(
+        interface.to_string(),
+    ))
+}
+
+#[cfg(any(
+    target_os = "android",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+fn set_dscp(socket: &Socket, target: IpAddr, dscp: Dscp) -> Result<(), CheckJitterError> {
+    match target {
+        IpAddr::V4(_) => socket.set_tos(dscp.traffic_class())?,
+        IpAddr::V6(_) => socket.set_tclass_v6(dscp.traffic_class())?,
+    }
+    Ok(())
+}
+
+#[cfg(not(any(
+    target_os = "android",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "netbsd",
+    target_os = "openbsd"
+)))]
+fn set_dscp(_socket: &Socket, _target: IpAddr, dscp: Dscp) -> Result<(), CheckJitterError> {
+    Err(CheckJitterError::DscpUnsupported(dscp))
+}
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn set_dont_fragment(socket: &Socket, target: IpAddr) -> Result<(), CheckJitterError> {
+    match target {
+        IpAddr::V4(_) => set_int_option(
+            socket,
+            libc::IPPROTO_IP,
+            libc::IP_MTU_DISCOVER,
+            libc::IP_PMTUDISC_DO,
+        )?,
+        IpAddr::V6(_) => set_int_option(
+            socket,
+            libc::IPPROTO_IPV6,
+            libc::IPV6_MTU_DISCOVER,
+            libc::IPV6_PMTUDISC_DO,
+        )?,
+    }
+    Ok(())
+}
+
+/// Set a socket option that socket2 has no setter for.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+pub(crate) fn set_int_option(
+    socket: &Socket,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: libc::c_int,
+) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    // SAFETY: The option value is a c_int that lives for the duration of the call, and its size
+    // is passed along with it.
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "android", target_os = "linux")))]
+fn set_dont_fragment(_socket: &Socket, _target: IpAddr) -> Result<(), CheckJitterError> {
+    Err(CheckJitterError::DontFragmentUnsupported)
+}
+
+/// Convert an error from sending a probe of `size` bytes, which is too large if fragmentation is
+/// disabled and it exceeds the MTU of the path.
+pub(crate) fn send_error(e: io::Error, size: usize) -> CheckJitterError {
+    match e.raw_os_error() {
+        Some(EMSGSIZE) => CheckJitterError::PacketTooLarge(size),
+        _ => e.into(),
+    }
+}
+
+#[cfg(test)]
+mod dscp_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("EF".parse(), Ok(Dscp(46)));
+        assert_eq!("af41".parse(), Ok(Dscp(34)));
+        assert_eq!("46".parse(), Ok(Dscp(46)));
+        assert!("AF44".parse::<Dscp>().is_err());
+        assert!("64".parse::<Dscp>().is_err());
+        assert_eq!(Dscp(46).to_string(), "EF");
+        assert_eq!(Dscp(3).to_string(), "3");
+    }
+
+    #[test]
+    fn test_with_ipv4() {
+        let target = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let options = SocketOptions {
+            dscp: Some(Dscp(46)),
+            ..Default::default()
+        };
+
+        let socket = new_socket(target, Type::DGRAM, Protocol::UDP, &options, false).unwrap();
+
+        assert_eq!(socket.tos().unwrap(), 0xb8);
+    }
+
+    #[test]
+    fn test_with_ipv6() {
+        let target = IpAddr::V6(Ipv6Addr::LOCALHOST);
+        let options = SocketOptions {
+            dscp: Some(Dscp(34)),
+            ..Default::default()
+        };
+
+        let socket = new_socket(target, Type::DGRAM, Protocol::UDP, &options, false).unwrap();
+
+        assert_eq!(socket.tclass_v6().unwrap(), 0x88);
+    }
+}
+
+#[cfg(test)]
+mod new_socket_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn options(source: IpAddr) -> SocketOptions {
+        SocketOptions {
+            source: Some(source),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_with_local_source() {
+        let source = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+        let socket =
+            new_socket(source, Type::DGRAM, Protocol::UDP, &options(source), false).unwrap();
+
+        let local = socket.local_addr().unwrap().as_socket().unwrap();
+        assert_eq!(local.ip(), source);
+    }
+
+    #[test]
+    fn test_with_foreign_source() {
+        // TEST-NET-1 (RFC 5737) is never assigned to a local interface.
+        let source = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+        let result = new_socket(
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            Type::DGRAM,
+            Protocol::UDP,
+            &options(source),
+            false,
+        );
+
+        assert_eq!(
+            result.err(),
+            Some(CheckJitterError::SourceAddressNotLocal(source))
+        );
+    }
+
+    #[test]
+    fn test_with_mismatched_source() {
+        let source = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let target = IpAddr::V6(Ipv6Addr::LOCALHOST);
+
+        let result = new_socket(target, Type::DGRAM, Protocol::UDP, &options(source), false);
+
+        assert_eq!(
+            result.err(),
+            Some(CheckJitterError::SourceAddressMismatch(source, target))
+        );
+    }
+
+    #[test]
+    fn test_with_ttl() {
+        let options = SocketOptions {
+            ttl: Some(3),
+            ..Default::default()
+        };
+
+        let v4 = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let socket = new_socket(v4, Type::DGRAM, Protocol::UDP, &options, false).unwrap();
+        assert_eq!(socket.ttl().unwrap(), 3);
+
+        let v6 = IpAddr::V6(Ipv6Addr::LOCALHOST);
+        let socket = new_socket(v6, Type::DGRAM, Protocol::UDP, &options, false).unwrap();
+        assert_eq!(socket.unicast_hops_v6().unwrap(), 3);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_with_dont_fragment() {
+        use std::net::UdpSocket;
+
+        let target = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let options = SocketOptions {
+            dont_fragment: true,
+            ..Default::default()
+        };
+        let receiver = UdpSocket::bind((target, 0)).unwrap();
+
+        let socket: UdpSocket = new_socket(target, Type::DGRAM, Protocol::UDP, &options, true)
+            .unwrap()
+            .into();
+        socket.connect(receiver.local_addr().unwrap()).unwrap();
+        // The loopback MTU is 65536 bytes, so only an oversized datagram is rejected.
+        let result = socket.send(&[0; 65_508]).map_err(|e| send_error(e, 65_508));
+
+        assert_eq!(result.err(), Some(CheckJitterError::PacketTooLarge(65_508)));
+    }
+}
@@ -0,0 +1,68 @@
+use crate::socket::new_socket;
+use crate::{CheckJitterError, Pinger, Reply, SocketOptions};
+use socket2::{Protocol, Type};
+use std::io::ErrorKind;
+use std::net::{Shutdown, SocketAddr};
+use std::time::{Duration, Instant};
+
+/// Measure the time it takes to complete a TCP three-way handshake with `addr`.
+///
+/// Returns `None` if the handshake did not complete within the timeout, which is counted as a
+/// lost probe in the same way as a lost ping.
+pub(crate) fn connect_time(
+    addr: SocketAddr,
+    options: &SocketOptions,
+    timeout: Duration,
+) -> Result<Option<Duration>, CheckJitterError> {
+    let socket = new_socket(addr.ip(), Type::STREAM, Protocol::TCP, options, false)?;
+    let start_time = Instant::now();
+
+    match socket.connect_timeout(&addr.into(), timeout) {
+        Ok(()) => {
+            let duration = Instant::now() - start_time;
+            // The connection is only used for timing, so close it right away.
+            let _ = socket.shutdown(Shutdown::Both);
+            Ok(Some(duration))
+        }
+        Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// A series of TCP handshakes with a single port, using a new connection for every probe.
+pub(crate) struct TcpConnect {
+    addr: SocketAddr,
+    options: SocketOptions,
+}
+
+impl TcpConnect {
+    pub(crate) fn new(addr: SocketAddr, options: &SocketOptions) -> Self {
+        TcpConnect {
+            addr,
+            options: options.clone(),
+        }
+    }
+}
+
+impl Pinger for TcpConnect {
+    fn probe(&mut self, timeout: Duration) -> Result<Option<Reply>, CheckJitterError> {
+        Ok(connect_time(self.addr, &self.options, timeout)?.map(Reply::from))
+    }
+}
+
+#[cfg(test)]
+mod connect_time_tests {
+    use super::*;
+    use std::net::{Ipv4Addr, TcpListener};
+
+    #[test]
+    fn test_with_local_listener() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let duration =
+            connect_time(addr, &SocketOptions::default(), Duration::from_secs(1)).unwrap();
+
+        assert!(duration.is_some());
+    }
+}
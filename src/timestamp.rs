@@ -0,0 +1,234 @@
+use crate::icmp::checksum;
+use crate::socket::new_socket;
+use crate::{CheckJitterError, Component, Pinger, Reply, SocketOptions};
+use log::debug;
+use socket2::{Protocol, Socket, Type};
+use std::io::{ErrorKind, Read};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const ICMP_TIMESTAMP_REQUEST: u8 = 13;
+const ICMP_TIMESTAMP_REPLY: u8 = 14;
+const MESSAGE_SIZE: usize = 20;
+const MILLIS_PER_DAY: i64 = 86_400_000;
+
+/// Milliseconds since midnight UT, as used by ICMP timestamp messages (RFC 792).
+fn millis_since_midnight() -> u32 {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    (since_epoch.as_millis() % MILLIS_PER_DAY as u128) as u32
+}
+
+/// The signed difference `later - earlier` in milliseconds, allowing for a wrap at midnight.
+///
+/// The high order bit of a timestamp is set by hosts that do not use UT, but the difference is
+/// still meaningful as long as both timestamps come from the same clock.
+fn millis_between(earlier: u32, later: u32) -> i64 {
+    let diff = (i64::from(later) - i64::from(earlier)).rem_euclid(MILLIS_PER_DAY);
+    if diff > MILLIS_PER_DAY / 2 {
+        diff - MILLIS_PER_DAY
+    } else {
+        diff
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct TimestampMessage {
+    kind: u8,
+    ident: u16,
+    seq: u16,
+    originate: u32,
+    receive: u32,
+    transmit: u32,
+}
+
+fn encode_message(message: &TimestampMessage) -> [u8; MESSAGE_SIZE] {
+    let mut buf = [0; MESSAGE_SIZE];
+    buf[0] = message.kind;
+    buf[4..6].copy_from_slice(&message.ident.to_be_bytes());
+    buf[6..8].copy_from_slice(&message.seq.to_be_bytes());
+    buf[8..12].copy_from_slice(&message.originate.to_be_bytes());
+    buf[12..16].copy_from_slice(&message.receive.to_be_bytes());
+    buf[16..20].copy_from_slice(&message.transmit.to_be_bytes());
+    let sum = checksum(&buf);
+    buf[2..4].copy_from_slice(&sum.to_be_bytes());
+    buf
+}
+
+/// Decode a timestamp message from an IPv4 packet as received on a raw socket.
+fn decode_message(packet: &[u8]) -> Option<TimestampMessage> {
+    let header_len = usize::from(packet.first()? & 0x0f) * 4;
+    let buf = packet.get(header_len..header_len + MESSAGE_SIZE)?;
+    Some(TimestampMessage {
+        kind: buf[0],
+        ident: u16::from_be_bytes(buf[4..6].try_into().ok()?),
+        seq: u16::from_be_bytes(buf[6..8].try_into().ok()?),
+        originate: u32::from_be_bytes(buf[8..12].try_into().ok()?),
+        receive: u32::from_be_bytes(buf[12..16].try_into().ok()?),
+        transmit: u32::from_be_bytes(buf[16..20].try_into().ok()?),
+    })
+}
+
+/// A series of ICMP timestamp requests (RFC 792) to a single IPv4 host, one per probe.
+pub(crate) struct TimestampSession {
+    socket: Socket,
+    ident: u16,
+    seq: u16,
+}
+
+impl TimestampSession {
+    pub(crate) fn connect(ip: IpAddr, options: &SocketOptions) -> Result<Self, CheckJitterError> {
+        if !ip.is_ipv4() {
+            return Err(CheckJitterError::TimestampRequiresIpv4(ip.to_string()));
+        }
+        let socket = new_socket(ip, Type::RAW, Protocol::ICMPV4, options, false)?;
+        socket.connect(&SocketAddr::new(ip, 0).into())?;
+
+        Ok(TimestampSession {
+            socket,
+            ident: rand::random(),
+            seq: 0,
+        })
+    }
+}
+
+impl Pinger for TimestampSession {
+    /// Send the next timestamp request and wait for its reply.
+    ///
+    /// The forward and reverse delays are derived from the timestamps of the reply, which have a
+    /// resolution of one millisecond and include the offset between the clocks of the two hosts.
+    fn probe(&mut self, timeout: Duration) -> Result<Option<Reply>, CheckJitterError> {
+        self.seq = self.seq.wrapping_add(1);
+        let request = TimestampMessage {
+            kind: ICMP_TIMESTAMP_REQUEST,
+            ident: self.ident,
+            seq: self.seq,
+            originate: millis_since_midnight(),
+            receive: 0,
+            transmit: 0,
+        };
+        let mut buf = [0; 2048];
+
+        let start_time = Instant::now();
+        self.socket.send(&encode_message(&request))?;
+
+        loop {
+            let remaining = match timeout.checked_sub(start_time.elapsed()) {
+                Some(remaining) if !remaining.is_zero() => remaining,
+                _ => return Ok(None),
+            };
+            self.socket.set_read_timeout(Some(remaining))?;
+
+            match (&self.socket).read(&mut buf) {
+                Ok(n) => {
+                    let elapsed = start_time.elapsed();
+                    let received = millis_since_midnight();
+                    match decode_message(&buf[..n]) {
+                        Some(reply)
+                            if reply.kind == ICMP_TIMESTAMP_REPLY
+                                && reply.ident == self.ident
+                                && reply.seq == self.seq =>
+                        {
+                            return Ok(Some(Reply {
+                                rtt: elapsed,
+                                components: components(&reply, received),
+                            }));
+                        }
+                        // Raw sockets receive every ICMP message, including our own requests
+                        // on the loopback interface and replies to other processes.
+                        _ => debug!("Discarding unrelated ICMP message of {} bytes", n),
+                    }
+                }
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    return Ok(None)
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+fn components(reply: &TimestampMessage, received: u32) -> Vec<(Component, i64)> {
+    let forward = millis_between(reply.originate, reply.receive);
+    let reverse = millis_between(reply.transmit, received);
+    vec![
+        (Component::Forward, forward * 1_000_000),
+        (Component::Reverse, reverse * 1_000_000),
+    ]
+}
+
+#[cfg(test)]
+mod timestamp_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_millis_between() {
+        assert_eq!(millis_between(1_000, 1_250), 250);
+        assert_eq!(millis_between(1_250, 1_000), -250);
+        assert_eq!(millis_between(86_399_900, 100), 200);
+        assert_eq!(millis_between(100, 86_399_900), -200);
+    }
+
+    #[test]
+    fn test_checksum() {
+        let buf = encode_message(&TimestampMessage {
+            kind: ICMP_TIMESTAMP_REQUEST,
+            ident: 0x1234,
+            seq: 1,
+            originate: 42,
+            receive: 0,
+            transmit: 0,
+        });
+
+        assert_eq!(checksum(&buf), 0);
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let message = TimestampMessage {
+            kind: ICMP_TIMESTAMP_REPLY,
+            ident: 7,
+            seq: 3,
+            originate: 1_000,
+            receive: 1_005,
+            transmit: 1_006,
+        };
+        let mut packet = vec![0x45];
+        packet.extend_from_slice(&[0; 19]);
+        packet.extend_from_slice(&encode_message(&message));
+
+        assert_eq!(decode_message(&packet), Some(message));
+        assert_eq!(decode_message(&packet[..30]), None);
+    }
+
+    #[test]
+    fn test_components() {
+        let reply = TimestampMessage {
+            kind: ICMP_TIMESTAMP_REPLY,
+            ident: 7,
+            seq: 3,
+            originate: 1_000,
+            receive: 1_005,
+            transmit: 1_006,
+        };
+
+        let expected = vec![
+            (Component::Forward, 5_000_000),
+            (Component::Reverse, -2_000_000),
+        ];
+
+        assert_eq!(components(&reply, 1_004), expected);
+    }
+
+    #[test]
+    fn test_with_ipv6() {
+        let result = TimestampSession::connect("::1".parse().unwrap(), &SocketOptions::default());
+
+        assert_eq!(
+            result.err(),
+            Some(CheckJitterError::TimestampRequiresIpv4("::1".to_string()))
+        );
+    }
+}
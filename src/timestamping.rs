@@ -0,0 +1,228 @@
+use crate::CheckJitterError;
+use socket2::Socket;
+use std::io;
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// Hardware and software timestamps of sent and received packets, where the timestamps of sent
+/// packets are queued without the packet itself.
+///
+/// Hardware timestamps are only reported by network cards that support them and have been
+/// configured to take them, e.g. with `hwstamp_ctl`. Software timestamps are always reported.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+const FLAGS: libc::c_uint = libc::SOF_TIMESTAMPING_TX_HARDWARE
+    | libc::SOF_TIMESTAMPING_RX_HARDWARE
+    | libc::SOF_TIMESTAMPING_RAW_HARDWARE
+    | libc::SOF_TIMESTAMPING_TX_SOFTWARE
+    | libc::SOF_TIMESTAMPING_RX_SOFTWARE
+    | libc::SOF_TIMESTAMPING_SOFTWARE
+    | libc::SOF_TIMESTAMPING_OPT_TSONLY;
+
+/// The timestamps of a packet, either of which may be missing.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Timestamps {
+    software: Option<Duration>,
+    hardware: Option<Duration>,
+}
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+impl Timestamps {
+    /// The hardware timestamp if the network card took one, or the software timestamp.
+    fn best(&self) -> Option<Duration> {
+        self.hardware.or(self.software)
+    }
+}
+
+/// Enable kernel timestamps (`SO_TIMESTAMPING`) of the packets sent and received on `socket`.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+pub(crate) fn enable(socket: &Socket) -> Result<(), CheckJitterError> {
+    crate::socket::set_int_option(
+        socket,
+        libc::SOL_SOCKET,
+        libc::SO_TIMESTAMPING,
+        FLAGS as libc::c_int,
+    )?;
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "android", target_os = "linux")))]
+pub(crate) fn enable(_socket: &Socket) -> Result<(), CheckJitterError> {
+    Err(CheckJitterError::KernelTimestampsUnsupported)
+}
+
+/// Receive a message into `buf`, returning its length, the address it was sent from and the
+/// time it was received, as a duration since the UNIX epoch. The time is taken by the network
+/// card if it supports hardware timestamps, and by the kernel otherwise.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+pub(crate) fn recv(
+    socket: &Socket,
+    buf: &mut [u8],
+) -> io::Result<(usize, Option<IpAddr>, Option<Duration>)> {
+    let (n, from, timestamps) = recv_msg(socket, buf, 0)?;
+    Ok((n, from, timestamps.best()))
+}
+
+#[cfg(not(any(target_os = "android", target_os = "linux")))]
+pub(crate) fn recv(
+    _socket: &Socket,
+    _buf: &mut [u8],
+) -> io::Result<(usize, Option<IpAddr>, Option<Duration>)> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// The time the last packet that a timestamp is queued for was sent, as a duration since the
+/// UNIX epoch. Every queued timestamp is consumed.
+///
+/// The network card and the kernel queue their timestamps of a packet separately, so the last
+/// hardware timestamp is preferred over the last software timestamp.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+pub(crate) fn last_sent(socket: &Socket) -> Option<Duration> {
+    let mut last = Timestamps::default();
+    while let Ok((_, _, timestamps)) =
+        recv_msg(socket, &mut [], libc::MSG_ERRQUEUE | libc::MSG_DONTWAIT)
+    {
+        last.software = timestamps.software.or(last.software);
+        last.hardware = timestamps.hardware.or(last.hardware);
+    }
+    last.best()
+}
+
+#[cfg(not(any(target_os = "android", target_os = "linux")))]
+pub(crate) fn last_sent(_socket: &Socket) -> Option<Duration> {
+    None
+}
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn recv_msg(
+    socket: &Socket,
+    buf: &mut [u8],
+    flags: libc::c_int,
+) -> io::Result<(usize, Option<IpAddr>, Timestamps)> {
+    use socket2::SockAddr;
+    use std::mem;
+    use std::os::fd::AsRawFd;
+
+    // Room for a few control messages, aligned for their headers.
+    let mut control = [0u64; 64];
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr().cast(),
+        iov_len: buf.len(),
+    };
+    // SAFETY: Both are plain C structs, for which all zeros is a valid value.
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_name = (&mut storage as *mut libc::sockaddr_storage).cast();
+    msg.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = mem::size_of_val(&control) as _;
+
+    // SAFETY: Every pointer in `msg` points to a buffer of the given length that lives for the
+    // duration of the call.
+    let n = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, flags) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: The kernel initialized `msg_namelen` bytes of the address.
+    let from = unsafe { SockAddr::new(storage, msg.msg_namelen) };
+    let mut timestamps = Timestamps::default();
+    // SAFETY: The control messages were written by the kernel to the buffer of `msg`, and are
+    // only read within their length.
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_TIMESTAMPING
+            {
+                // The software timestamp is the first of three and the raw hardware timestamp
+                // the last, the second is deprecated. Timestamps that were not taken are zero.
+                let ts: [libc::timespec; 3] =
+                    std::ptr::read_unaligned(libc::CMSG_DATA(cmsg).cast());
+                timestamps.software = to_duration(&ts[0]).or(timestamps.software);
+                timestamps.hardware = to_duration(&ts[2]).or(timestamps.hardware);
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+
+    Ok((
+        n as usize,
+        from.as_socket().map(|addr| addr.ip()),
+        timestamps,
+    ))
+}
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn to_duration(ts: &libc::timespec) -> Option<Duration> {
+    if ts.tv_sec == 0 && ts.tv_nsec == 0 {
+        None
+    } else {
+        Some(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod timestamping_tests {
+    use super::*;
+    use socket2::{Domain, Protocol, Type};
+    use std::net::{Ipv4Addr, SocketAddr};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn udp_socket() -> Socket {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP)).unwrap();
+        socket
+            .bind(&SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0).into())
+            .unwrap();
+        enable(&socket).unwrap();
+        socket
+    }
+
+    #[test]
+    fn test_prefers_hardware_timestamp() {
+        let software = Some(Duration::from_secs(2));
+        let hardware = Some(Duration::from_secs(1));
+
+        assert_eq!(Timestamps { software, hardware }.best(), hardware);
+        assert_eq!(
+            Timestamps {
+                software,
+                hardware: None
+            }
+            .best(),
+            software
+        );
+    }
+
+    #[test]
+    fn test_send_and_receive() {
+        let sender = udp_socket();
+        let receiver = udp_socket();
+        let mut buf = [0; 16];
+
+        // The kernel enables receive timestamps in the background the first time a socket asks
+        // for them, so the first packets may arrive without one.
+        for _ in 0..10 {
+            let before = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+            sender
+                .send_to(b"probe", &receiver.local_addr().unwrap())
+                .unwrap();
+            let (n, from, received) = recv(&receiver, &mut buf).unwrap();
+            let sent = last_sent(&sender).unwrap();
+            let after = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+
+            assert_eq!(&buf[..n], b"probe");
+            assert_eq!(from, Some(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+            assert_eq!(last_sent(&sender), None);
+            // Both are taken on the loopback interface, in no guaranteed order.
+            assert!(before <= sent && sent <= after);
+            if let Some(received) = received {
+                assert!(before <= received && received <= after);
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("No packet was received with a timestamp");
+    }
+}
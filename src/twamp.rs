@@ -0,0 +1,370 @@
+use crate::payload::{fill, payload_size};
+use crate::sequence::SequenceTracker;
+use crate::socket::{new_socket, send_error};
+use crate::{
+    CheckJitterError, Component, PayloadPattern, Pinger, Reply, SequenceErrors, SocketOptions,
+};
+use log::{debug, error, info};
+use socket2::{Protocol, Type};
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Seconds from the NTP epoch (1900) to the UNIX epoch (1970).
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+/// The unauthenticated test packet of the session sender is padded to this size, so that the
+/// reflected packet fits without truncation (RFC 5357, section 4.2.1).
+const TEST_PACKET_SIZE: usize = 41;
+const REFLECTED_SIZE: usize = 41;
+/// Synchronized bit unset, no scale and a multiplier of one.
+const ERROR_ESTIMATE: u16 = 0x0001;
+
+/// An NTP timestamp in 32.32 fixed point format.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Timestamp(u64);
+
+impl Timestamp {
+    fn now() -> Self {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let seconds = since_epoch.as_secs() + NTP_UNIX_OFFSET;
+        let fraction = (u64::from(since_epoch.subsec_nanos()) << 32) / 1_000_000_000;
+        Timestamp((seconds << 32) | fraction)
+    }
+
+    fn as_nanos(&self) -> i128 {
+        let seconds = i128::from(self.0 >> 32);
+        let fraction = i128::from(self.0 & 0xffff_ffff);
+        seconds * 1_000_000_000 + ((fraction * 1_000_000_000) >> 32)
+    }
+
+    /// The signed difference `self - earlier` in nanoseconds.
+    fn nanos_since(&self, earlier: Timestamp) -> i64 {
+        (self.as_nanos() - earlier.as_nanos()) as i64
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct TestPacket {
+    seq: u32,
+    timestamp: Timestamp,
+}
+
+fn encode_test_packet(packet: &TestPacket) -> [u8; TEST_PACKET_SIZE] {
+    let mut buf = [0; TEST_PACKET_SIZE];
+    buf[0..4].copy_from_slice(&packet.seq.to_be_bytes());
+    buf[4..12].copy_from_slice(&packet.timestamp.0.to_be_bytes());
+    buf[12..14].copy_from_slice(&ERROR_ESTIMATE.to_be_bytes());
+    buf
+}
+
+fn decode_test_packet(buf: &[u8]) -> Option<TestPacket> {
+    if buf.len() < 14 {
+        return None;
+    }
+    Some(TestPacket {
+        seq: u32::from_be_bytes(buf[0..4].try_into().ok()?),
+        timestamp: Timestamp(u64::from_be_bytes(buf[4..12].try_into().ok()?)),
+    })
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct ReflectedPacket {
+    seq: u32,
+    timestamp: Timestamp,
+    receive_timestamp: Timestamp,
+    sender: TestPacket,
+}
+
+fn encode_reflected_packet(packet: &ReflectedPacket, len: usize) -> Vec<u8> {
+    let mut buf = vec![0; len.max(REFLECTED_SIZE)];
+    buf[0..4].copy_from_slice(&packet.seq.to_be_bytes());
+    buf[4..12].copy_from_slice(&packet.timestamp.0.to_be_bytes());
+    buf[12..14].copy_from_slice(&ERROR_ESTIMATE.to_be_bytes());
+    buf[16..24].copy_from_slice(&packet.receive_timestamp.0.to_be_bytes());
+    buf[24..28].copy_from_slice(&packet.sender.seq.to_be_bytes());
+    buf[28..36].copy_from_slice(&packet.sender.timestamp.0.to_be_bytes());
+    buf[36..38].copy_from_slice(&ERROR_ESTIMATE.to_be_bytes());
+    // The sender TTL is not available from a UDP socket, so assume the packet was not routed.
+    buf[40] = 255;
+    buf
+}
+
+fn decode_reflected_packet(buf: &[u8]) -> Option<ReflectedPacket> {
+    if buf.len() < REFLECTED_SIZE {
+        return None;
+    }
+    Some(ReflectedPacket {
+        seq: u32::from_be_bytes(buf[0..4].try_into().ok()?),
+        timestamp: Timestamp(u64::from_be_bytes(buf[4..12].try_into().ok()?)),
+        receive_timestamp: Timestamp(u64::from_be_bytes(buf[16..24].try_into().ok()?)),
+        sender: decode_test_packet(&buf[24..38])?,
+    })
+}
+
+/// The index of the test packet with sequence number `seq` in the order they were sent.
+fn index(seq: u32) -> usize {
+    seq.wrapping_sub(1) as usize
+}
+
+/// A TWAMP-Light (RFC 5357, appendix I) session sender, sending one test packet per probe.
+pub(crate) struct SessionSender {
+    socket: UdpSocket,
+    seq: u32,
+    size: usize,
+    pattern: Option<PayloadPattern>,
+    tracker: SequenceTracker,
+}
+
+impl SessionSender {
+    pub(crate) fn connect(
+        addr: SocketAddr,
+        options: &SocketOptions,
+    ) -> Result<Self, CheckJitterError> {
+        let size = payload_size(options, TEST_PACKET_SIZE, TEST_PACKET_SIZE)?;
+        let socket: UdpSocket =
+            new_socket(addr.ip(), Type::DGRAM, Protocol::UDP, options, true)?.into();
+        socket.connect(addr)?;
+
+        Ok(SessionSender {
+            socket,
+            seq: 0,
+            size,
+            pattern: options.payload_pattern.clone(),
+            tracker: SequenceTracker::default(),
+        })
+    }
+}
+
+impl Pinger for SessionSender {
+    /// Send the next test packet and wait for it to be reflected.
+    ///
+    /// The round trip time excludes the time the packet spent in the reflector. The forward and
+    /// reverse delays include the offset between the clocks of the two hosts.
+    fn probe(&mut self, timeout: Duration) -> Result<Option<Reply>, CheckJitterError> {
+        self.seq = self.seq.wrapping_add(1);
+        let request = TestPacket {
+            seq: self.seq,
+            timestamp: Timestamp::now(),
+        };
+        // The packet padding beyond the fixed size is filled like the payload of other probes.
+        let mut packet = vec![0; self.size];
+        packet[..TEST_PACKET_SIZE].copy_from_slice(&encode_test_packet(&request));
+        fill(&mut packet[TEST_PACKET_SIZE..], self.pattern.as_ref());
+        let mut buf = vec![0; self.size];
+
+        let start_time = Instant::now();
+        self.socket
+            .send(&packet)
+            .map_err(|e| send_error(e, packet.len()))?;
+        self.tracker.sent();
+
+        loop {
+            let remaining = match timeout.checked_sub(start_time.elapsed()) {
+                Some(remaining) if !remaining.is_zero() => remaining,
+                _ => return Ok(None),
+            };
+            self.socket.set_read_timeout(Some(remaining))?;
+
+            match self.socket.recv(&mut buf) {
+                Ok(n) => {
+                    let elapsed = start_time.elapsed();
+                    let received = Timestamp::now();
+                    match decode_reflected_packet(&buf[..n]) {
+                        Some(reflected) if reflected.sender.seq == self.seq => {
+                            self.tracker.received(index(self.seq));
+                            return Ok(Some(reply(&request, &reflected, elapsed, received)));
+                        }
+                        Some(reflected) => {
+                            debug!(
+                                "Discarding out of order reply with sequence number {}, expected {}",
+                                reflected.sender.seq, self.seq
+                            );
+                            self.tracker.received(index(reflected.sender.seq));
+                        }
+                        None => debug!("Discarding unrecognized reply of {} bytes", n),
+                    }
+                }
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    return Ok(None)
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn sequence_errors(&self) -> Option<SequenceErrors> {
+        Some(self.tracker.errors())
+    }
+}
+
+fn reply(
+    request: &TestPacket,
+    reflected: &ReflectedPacket,
+    elapsed: Duration,
+    received: Timestamp,
+) -> Reply {
+    let residence = reflected.timestamp.nanos_since(reflected.receive_timestamp);
+    let residence = Duration::from_nanos(residence.max(0) as u64);
+
+    Reply {
+        rtt: elapsed.saturating_sub(residence),
+        components: vec![
+            (
+                Component::Forward,
+                reflected.receive_timestamp.nanos_since(request.timestamp),
+            ),
+            (
+                Component::Reverse,
+                received.nanos_since(reflected.timestamp),
+            ),
+        ],
+    }
+}
+
+/// Reflect test packets until the process is stopped. An error with one packet, such as a port
+/// unreachable from a sender that went away, is logged and does not stop the reflector.
+fn serve(socket: UdpSocket) -> ! {
+    let mut buf = [0; 65_535];
+    let mut seq: u32 = 0;
+    loop {
+        let (n, peer) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e) => {
+                error!("Failed to receive a test packet: {}", e);
+                continue;
+            }
+        };
+        let receive_timestamp = Timestamp::now();
+        let Some(sender) = decode_test_packet(&buf[..n]) else {
+            debug!(
+                "Discarding unrecognized packet of {} bytes from {}",
+                n, peer
+            );
+            continue;
+        };
+        debug!(
+            "Reflecting test packet with sequence number {} to {}",
+            sender.seq, peer
+        );
+        let reflected = ReflectedPacket {
+            seq,
+            timestamp: Timestamp::now(),
+            receive_timestamp,
+            sender,
+        };
+        if let Err(e) = socket.send_to(&encode_reflected_packet(&reflected, n), peer) {
+            error!(
+                "Failed to reflect test packet with sequence number {} to {}: {}",
+                reflected.sender.seq, peer, e
+            );
+            continue;
+        }
+        seq = seq.wrapping_add(1);
+    }
+}
+
+/// Run a TWAMP-Light session reflector (RFC 5357, appendix I) on `addr` for the TWAMP probe mode
+/// to measure against.
+///
+/// Every test packet received is timestamped and sent back to its sender. This function only
+/// returns if the reflector cannot be started.
+pub fn run_twamp_reflector(addr: SocketAddr) -> Result<(), CheckJitterError> {
+    let socket = UdpSocket::bind(addr)?;
+    info!(
+        "TWAMP-Light reflector listening on {}",
+        socket.local_addr()?
+    );
+    serve(socket)
+}
+
+#[cfg(test)]
+mod twamp_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::net::Ipv4Addr;
+    use std::thread;
+
+    fn spawn_reflector() -> SocketAddr {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = socket.local_addr().unwrap();
+        thread::spawn(move || serve(socket));
+        addr
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let t1 = Timestamp((3_900_000_000 << 32) | (1 << 31));
+        let t2 = Timestamp((3_900_000_001 << 32) | (1 << 30));
+
+        assert_eq!(t2.nanos_since(t1), 750_000_000);
+        assert_eq!(t1.nanos_since(t2), -750_000_000);
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let sender = TestPacket {
+            seq: 42,
+            timestamp: Timestamp(1),
+        };
+        let reflected = ReflectedPacket {
+            seq: 7,
+            timestamp: Timestamp(3),
+            receive_timestamp: Timestamp(2),
+            sender: sender.clone(),
+        };
+
+        assert_eq!(
+            decode_test_packet(&encode_test_packet(&sender)),
+            Some(sender)
+        );
+        let buf = encode_reflected_packet(&reflected, TEST_PACKET_SIZE);
+        assert_eq!(buf.len(), REFLECTED_SIZE);
+        assert_eq!(decode_reflected_packet(&buf), Some(reflected));
+        assert_eq!(decode_reflected_packet(&[0; 14]), None);
+    }
+
+    #[test]
+    fn test_reply() {
+        let request = TestPacket {
+            seq: 1,
+            timestamp: Timestamp(10 << 32),
+        };
+        let reflected = ReflectedPacket {
+            seq: 0,
+            timestamp: Timestamp(13 << 32),
+            receive_timestamp: Timestamp(12 << 32),
+            sender: request.clone(),
+        };
+
+        let expected = Reply {
+            rtt: Duration::from_secs(3),
+            components: vec![
+                (Component::Forward, 2_000_000_000),
+                (Component::Reverse, 1_000_000_000),
+            ],
+        };
+
+        assert_eq!(
+            reply(
+                &request,
+                &reflected,
+                Duration::from_secs(4),
+                Timestamp(14 << 32)
+            ),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_with_reflector() {
+        let addr = spawn_reflector();
+        let mut sender = SessionSender::connect(addr, &SocketOptions::default()).unwrap();
+
+        for _ in 0..3 {
+            let reply = sender.probe(Duration::from_secs(1)).unwrap().unwrap();
+            assert_eq!(reply.components.len(), 2);
+        }
+    }
+}
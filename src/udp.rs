@@ -0,0 +1,264 @@
+use crate::payload::{fill, payload_size};
+use crate::sequence::SequenceTracker;
+use crate::socket::{new_socket, send_error};
+use crate::{CheckJitterError, PayloadPattern, Pinger, Reply, SequenceErrors, SocketOptions};
+use log::{debug, error, info};
+use socket2::{Protocol, Type};
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// Identifies probes sent by check_jitter, so that unrelated datagrams can be discarded.
+const MAGIC: &[u8; 4] = b"CJIT";
+const PROBE_SIZE: usize = MAGIC.len() + 4;
+
+fn encode_probe(seq: u32) -> [u8; PROBE_SIZE] {
+    let mut buf = [0; PROBE_SIZE];
+    buf[..MAGIC.len()].copy_from_slice(MAGIC);
+    buf[MAGIC.len()..].copy_from_slice(&seq.to_be_bytes());
+    buf
+}
+
+fn decode_probe(buf: &[u8]) -> Option<u32> {
+    if buf.len() < PROBE_SIZE || &buf[..MAGIC.len()] != MAGIC {
+        return None;
+    }
+    let seq = buf[MAGIC.len()..PROBE_SIZE].try_into().ok()?;
+    Some(u32::from_be_bytes(seq))
+}
+
+/// The index of the datagram with sequence number `seq` in the order they were sent.
+fn index(seq: u32) -> usize {
+    seq.wrapping_sub(1) as usize
+}
+
+/// A UDP echo (RFC 862) session, sending one sequenced datagram per probe.
+pub(crate) struct UdpEcho {
+    socket: UdpSocket,
+    seq: u32,
+    size: usize,
+    pattern: Option<PayloadPattern>,
+    tracker: SequenceTracker,
+}
+
+impl UdpEcho {
+    pub(crate) fn connect(
+        addr: SocketAddr,
+        options: &SocketOptions,
+    ) -> Result<Self, CheckJitterError> {
+        let size = payload_size(options, PROBE_SIZE, PROBE_SIZE)?;
+        let socket: UdpSocket =
+            new_socket(addr.ip(), Type::DGRAM, Protocol::UDP, options, true)?.into();
+        socket.connect(addr)?;
+
+        Ok(UdpEcho {
+            socket,
+            seq: 0,
+            size,
+            pattern: options.payload_pattern.clone(),
+            tracker: SequenceTracker::default(),
+        })
+    }
+}
+
+impl Pinger for UdpEcho {
+    /// Send the next datagram and wait for its echo.
+    ///
+    /// Replies to earlier datagrams that arrive while waiting were already counted as lost and
+    /// are discarded, as are datagrams that were not sent by check_jitter.
+    fn probe(&mut self, timeout: Duration) -> Result<Option<Reply>, CheckJitterError> {
+        self.seq = self.seq.wrapping_add(1);
+        let mut request = vec![0; self.size];
+        request[..PROBE_SIZE].copy_from_slice(&encode_probe(self.seq));
+        fill(&mut request[PROBE_SIZE..], self.pattern.as_ref());
+        let mut buf = vec![0; self.size];
+
+        let start_time = Instant::now();
+        self.socket
+            .send(&request)
+            .map_err(|e| send_error(e, request.len()))?;
+        self.tracker.sent();
+
+        loop {
+            let remaining = match timeout.checked_sub(start_time.elapsed()) {
+                Some(remaining) if !remaining.is_zero() => remaining,
+                _ => return Ok(None),
+            };
+            self.socket.set_read_timeout(Some(remaining))?;
+
+            match self.socket.recv(&mut buf) {
+                Ok(n) => match decode_probe(&buf[..n]) {
+                    Some(seq) if seq == self.seq => {
+                        self.tracker.received(index(seq));
+                        return Ok(Some(Reply::from(Instant::now() - start_time)));
+                    }
+                    Some(seq) => {
+                        debug!(
+                            "Discarding out of order reply with sequence number {}, expected {}",
+                            seq, self.seq
+                        );
+                        self.tracker.received(index(seq));
+                    }
+                    None => debug!("Discarding unrecognized reply of {} bytes", n),
+                },
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    return Ok(None)
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn sequence_errors(&self) -> Option<SequenceErrors> {
+        Some(self.tracker.errors())
+    }
+}
+
+/// Echo datagrams until the process is stopped. An error with one datagram, such as a port
+/// unreachable from a sender that went away, is logged and does not stop the responder.
+fn serve(socket: UdpSocket) -> ! {
+    let mut buf = [0; 65_535];
+    loop {
+        let (n, peer) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e) => {
+                error!("Failed to receive a datagram: {}", e);
+                continue;
+            }
+        };
+        debug!("Echoing {} bytes to {}", n, peer);
+        if let Err(e) = socket.send_to(&buf[..n], peer) {
+            error!("Failed to echo {} bytes to {}: {}", n, peer, e);
+        }
+    }
+}
+
+/// Run a UDP echo responder (RFC 862) on `addr` for the UDP probe mode to measure against.
+///
+/// Every datagram received is sent back unchanged to its sender. This function only returns if
+/// the responder cannot be started.
+pub fn run_udp_responder(addr: SocketAddr) -> Result<(), CheckJitterError> {
+    let socket = UdpSocket::bind(addr)?;
+    info!("UDP echo responder listening on {}", socket.local_addr()?);
+    serve(socket)
+}
+
+#[cfg(test)]
+mod udp_echo_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::net::Ipv4Addr;
+    use std::thread;
+
+    fn spawn_responder() -> SocketAddr {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = socket.local_addr().unwrap();
+        thread::spawn(move || serve(socket));
+        addr
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        assert_eq!(decode_probe(&encode_probe(42)), Some(42));
+        assert_eq!(decode_probe(b"CJIT"), None);
+        assert_eq!(decode_probe(b"XXXX\0\0\0\x01"), None);
+    }
+
+    #[test]
+    fn test_with_responder() {
+        let addr = spawn_responder();
+        let mut echo = UdpEcho::connect(addr, &SocketOptions::default()).unwrap();
+
+        for _ in 0..3 {
+            let duration = echo.probe(Duration::from_secs(1)).unwrap();
+            assert!(duration.is_some());
+        }
+    }
+
+    #[test]
+    fn test_with_late_reply() {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = socket.local_addr().unwrap();
+        // Hold back the echo of the first datagram and send it just before the second one.
+        thread::spawn(move || {
+            let mut first = [0; PROBE_SIZE];
+            let mut second = [0; PROBE_SIZE];
+            let (_, peer) = socket.recv_from(&mut first).unwrap();
+            socket.recv_from(&mut second).unwrap();
+            socket.send_to(&first, peer).unwrap();
+            socket.send_to(&second, peer).unwrap();
+        });
+        let mut echo = UdpEcho::connect(addr, &SocketOptions::default()).unwrap();
+
+        let first = echo.probe(Duration::from_millis(100)).unwrap();
+        let second = echo.probe(Duration::from_secs(1)).unwrap();
+
+        assert_eq!(first, None);
+        assert!(second.is_some());
+        assert_eq!(echo.sequence_errors(), Some(SequenceErrors::default()));
+    }
+
+    #[test]
+    fn test_with_duplicate_reply() {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = socket.local_addr().unwrap();
+        // Echo the first datagram twice.
+        thread::spawn(move || {
+            let mut buf = [0; PROBE_SIZE];
+            let (_, peer) = socket.recv_from(&mut buf).unwrap();
+            socket.send_to(&buf, peer).unwrap();
+            socket.send_to(&buf, peer).unwrap();
+            let (_, peer) = socket.recv_from(&mut buf).unwrap();
+            socket.send_to(&buf, peer).unwrap();
+        });
+        let mut echo = UdpEcho::connect(addr, &SocketOptions::default()).unwrap();
+
+        assert!(echo.probe(Duration::from_secs(1)).unwrap().is_some());
+        assert!(echo.probe(Duration::from_secs(1)).unwrap().is_some());
+        assert_eq!(
+            echo.sequence_errors(),
+            Some(SequenceErrors {
+                reordered: 0,
+                duplicates: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_with_payload() {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = socket.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let mut buf = [0; 512];
+            let (n, peer) = socket.recv_from(&mut buf).unwrap();
+            socket.send_to(&buf[..n], peer).unwrap();
+            buf[..n].to_vec()
+        });
+        let options = SocketOptions {
+            payload_size: Some(12),
+            payload_pattern: Some("abcd".parse().unwrap()),
+            ..Default::default()
+        };
+        let mut echo = UdpEcho::connect(addr, &options).unwrap();
+
+        assert!(echo.probe(Duration::from_secs(1)).unwrap().is_some());
+        let received = handle.join().unwrap();
+        assert_eq!(received[..PROBE_SIZE], encode_probe(1));
+        assert_eq!(received[PROBE_SIZE..], [0xab, 0xcd, 0xab, 0xcd]);
+    }
+
+    #[test]
+    fn test_with_too_small_payload() {
+        let options = SocketOptions {
+            payload_size: Some(4),
+            ..Default::default()
+        };
+
+        let result = UdpEcho::connect((Ipv4Addr::LOCALHOST, 7).into(), &options);
+
+        assert_eq!(
+            result.err(),
+            Some(CheckJitterError::PayloadTooSmall(4, PROBE_SIZE))
+        );
+    }
+}
@@ -40,6 +40,449 @@ fn test_cli_no_args() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[test]
+fn test_cli_mismatched_thresholds() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("check_jitter")?;
+
+    cmd.arg("-H")
+        .arg("127.0.0.1")
+        .arg("-a")
+        .arg("average,median,max")
+        .arg("-w")
+        .arg("10,20");
+
+    cmd.assert()
+        .code(predicate::eq(3))
+        .stdout(predicate::str::starts_with(
+            "UNKNOWN - Got 2 warning thresholds for 3 aggregation methods.",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_with_tcp() -> Result<(), Box<dyn std::error::Error>> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+    let mut cmd = Command::cargo_bin("check_jitter")?;
+
+    cmd.arg("-H")
+        .arg("127.0.0.1")
+        .arg("--tcp")
+        .arg(port.to_string())
+        .arg("-w")
+        .arg("100")
+        .arg("-c")
+        .arg("200")
+        .arg("-vv");
+
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::is_match(format!("Socket type: +TCP port {}", port)).unwrap())
+        .stdout(predicate::str::starts_with("OK - Average Jitter:"));
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_with_full_loss() -> Result<(), Box<dyn std::error::Error>> {
+    // A socket that never echoes the datagrams sent to it.
+    let silent = std::net::UdpSocket::bind("127.0.0.1:0")?;
+    let port = silent.local_addr()?.port();
+
+    let mut cmd = Command::cargo_bin("check_jitter")?;
+
+    cmd.arg("-H")
+        .arg("127.0.0.1")
+        .arg("--udp")
+        .arg(port.to_string())
+        .arg("-s")
+        .arg("3")
+        .arg("-t")
+        .arg("50")
+        .arg("-w")
+        .arg("100")
+        .arg("--loss-critical")
+        .arg("50");
+
+    cmd.assert()
+        .code(predicate::eq(2))
+        .stdout(predicate::str::starts_with("CRITICAL - Packet Loss: 100%"))
+        .stdout(predicate::str::contains("'Packet Loss'=100%;;0:50;0;100"))
+        .stdout(predicate::str::contains("Jitter").not());
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_with_all_addresses() -> Result<(), Box<dyn std::error::Error>> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+
+    let mut cmd = Command::cargo_bin("check_jitter")?;
+
+    cmd.arg("-H")
+        .arg("127.0.0.1")
+        .arg("--tcp")
+        .arg(port.to_string())
+        .arg("--all-addresses")
+        .arg("--overall-state")
+        .arg("best")
+        .arg("-w")
+        .arg("100")
+        .arg("-c")
+        .arg("200");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "OK - 127.0.0.1 Average Jitter:",
+        ))
+        .stdout(predicate::str::contains("'127.0.0.1 Packet Loss'=0%"));
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_with_missing_address_family() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("check_jitter")?;
+
+    cmd.arg("-H")
+        .arg("127.0.0.1")
+        .arg("-6")
+        .arg("--tcp")
+        .arg("80")
+        .arg("-w")
+        .arg("100")
+        .arg("-c")
+        .arg("200");
+
+    cmd.assert()
+        .code(predicate::eq(3))
+        .stdout(predicate::str::contains(
+            "No IPv6 address found for: 127.0.0.1",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_with_foreign_source_address() -> Result<(), Box<dyn std::error::Error>> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+
+    let mut cmd = Command::cargo_bin("check_jitter")?;
+
+    cmd.arg("-H")
+        .arg("127.0.0.1")
+        .arg("--tcp")
+        .arg(port.to_string())
+        .arg("--source")
+        .arg("192.0.2.1")
+        .arg("-w")
+        .arg("100");
+
+    cmd.assert()
+        .code(predicate::eq(3))
+        .stdout(predicate::str::contains(
+            "The source address 192.0.2.1 is not assigned to a local interface",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_with_dscp() -> Result<(), Box<dyn std::error::Error>> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+
+    let mut cmd = Command::cargo_bin("check_jitter")?;
+
+    cmd.arg("-H")
+        .arg("127.0.0.1")
+        .arg("--tcp")
+        .arg(port.to_string())
+        .arg("--dscp")
+        .arg("af41")
+        .arg("-w")
+        .arg("100");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("'Average Jitter (DSCP AF41)'="));
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_with_deadline() -> Result<(), Box<dyn std::error::Error>> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+
+    let mut cmd = Command::cargo_bin("check_jitter")?;
+
+    // Only the first sample fits before the deadline.
+    cmd.arg("-H")
+        .arg("127.0.0.1")
+        .arg("--tcp")
+        .arg(port.to_string())
+        .arg("-m")
+        .arg("1000")
+        .arg("-M")
+        .arg("1000")
+        .arg("--deadline")
+        .arg("500")
+        .arg("-w")
+        .arg("100");
+
+    cmd.assert()
+        .code(predicate::eq(3))
+        .stdout(predicate::str::contains(
+            "UNKNOWN - The deadline of 500ms passed before enough samples were collected",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_with_invalid_dscp() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("check_jitter")?;
+
+    cmd.arg("-H")
+        .arg("127.0.0.1")
+        .arg("--dscp")
+        .arg("AF44")
+        .arg("-w")
+        .arg("100");
+
+    cmd.assert()
+        .code(predicate::eq(3))
+        .stdout(predicate::str::contains("'AF44' is not a valid DSCP name"));
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_with_too_small_payload() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("check_jitter")?;
+
+    cmd.arg("-H")
+        .arg("127.0.0.1")
+        .arg("--udp")
+        .arg("7")
+        .arg("--payload-size")
+        .arg("4")
+        .arg("-w")
+        .arg("100");
+
+    cmd.assert()
+        .code(predicate::eq(3))
+        .stdout(predicate::str::contains(
+            "The payload size of 4 bytes is smaller than the 8 bytes the probe requires",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_kernel_timestamps_with_fixed_rate() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("check_jitter")?;
+
+    cmd.arg("-H")
+        .arg("127.0.0.1")
+        .arg("--kernel-timestamps")
+        .arg("--fixed-rate")
+        .arg("10")
+        .arg("-w")
+        .arg("100");
+
+    cmd.assert()
+        .code(predicate::eq(3))
+        .stdout(predicate::str::contains("cannot be used with"));
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_with_udp_responder() -> Result<(), Box<dyn std::error::Error>> {
+    // Find a free port for the responder to listen on.
+    let port = std::net::UdpSocket::bind("127.0.0.1:0")?
+        .local_addr()?
+        .port();
+    let mut responder = Command::cargo_bin("check_jitter")?
+        .arg("--responder")
+        .arg("-H")
+        .arg("127.0.0.1")
+        .arg("--udp")
+        .arg(port.to_string())
+        .spawn()?;
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    let mut cmd = Command::cargo_bin("check_jitter")?;
+
+    cmd.arg("-H")
+        .arg("127.0.0.1")
+        .arg("--udp")
+        .arg(port.to_string())
+        .arg("-w")
+        .arg("100")
+        .arg("-c")
+        .arg("200");
+
+    let assert = cmd.assert();
+    responder.kill()?;
+
+    assert
+        .success()
+        .stdout(predicate::str::starts_with("OK - Average Jitter:"));
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_with_twamp_reflector() -> Result<(), Box<dyn std::error::Error>> {
+    // Find a free port for the reflector to listen on.
+    let port = std::net::UdpSocket::bind("127.0.0.1:0")?
+        .local_addr()?
+        .port();
+    let mut reflector = Command::cargo_bin("check_jitter")?
+        .arg("--responder")
+        .arg("-H")
+        .arg("127.0.0.1")
+        .arg("--twamp")
+        .arg(port.to_string())
+        .spawn()?;
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    let mut cmd = Command::cargo_bin("check_jitter")?;
+
+    cmd.arg("-H")
+        .arg("127.0.0.1")
+        .arg("--twamp")
+        .arg(port.to_string())
+        .arg("--threshold")
+        .arg("metric=forward_avg_jitter,warn=100,crit=200");
+
+    let assert = cmd.assert();
+    reflector.kill()?;
+
+    assert
+        .success()
+        .stdout(predicate::str::contains("Forward Average Jitter:"))
+        .stdout(predicate::str::contains("'Reverse Average Jitter'="));
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_with_http() -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/", listener.local_addr()?);
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf);
+            let _ = stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n");
+        }
+    });
+
+    let mut cmd = Command::cargo_bin("check_jitter")?;
+
+    cmd.arg("--http")
+        .arg(&url)
+        .arg("-s")
+        .arg("5")
+        .arg("--threshold")
+        .arg("metric=ttfb_avg_jitter,warn=100,crit=200")
+        .arg("-vv");
+
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::is_match("Socket type: +HTTP http://127.0.0.1:").unwrap())
+        .stdout(predicate::str::contains("TTFB Average Jitter:"))
+        .stdout(predicate::str::contains("'Connect Average Jitter'="))
+        .stdout(predicate::str::contains("TLS").not());
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_with_dns() -> Result<(), Box<dyn std::error::Error>> {
+    // A stub DNS server that answers every query with an empty response.
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0")?;
+    let port = socket.local_addr()?.port();
+    std::thread::spawn(move || {
+        let mut buf = [0; 512];
+        while let Ok((n, peer)) = socket.recv_from(&mut buf) {
+            buf[2] |= 0x80;
+            let _ = socket.send_to(&buf[..n], peer);
+        }
+    });
+
+    let mut cmd = Command::cargo_bin("check_jitter")?;
+
+    cmd.arg("-H")
+        .arg("127.0.0.1")
+        .arg("--dns")
+        .arg("example.com")
+        .arg("--dns-type")
+        .arg("aaaa")
+        .arg("--dns-port")
+        .arg(port.to_string())
+        .arg("-w")
+        .arg("100")
+        .arg("-c")
+        .arg("200")
+        .arg("-vv");
+
+    cmd.assert()
+        .success()
+        .stderr(
+            predicate::str::is_match(format!(
+                "Socket type: +DNS example.com AAAA on port {}",
+                port
+            ))
+            .unwrap(),
+        )
+        .stdout(predicate::str::starts_with("OK - Average Jitter:"));
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_icmp_timestamp_with_ipv6() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("check_jitter")?;
+
+    cmd.arg("-H")
+        .arg("::1")
+        .arg("--icmp-timestamp")
+        .arg("-w")
+        .arg("100")
+        .arg("-c")
+        .arg("200");
+
+    cmd.assert()
+        .code(predicate::eq(3))
+        .stdout(predicate::str::contains("require an IPv4 address"));
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_responder_requires_udp() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("check_jitter")?;
+
+    cmd.arg("--responder");
+
+    cmd.assert()
+        .code(predicate::eq(3))
+        .stdout(predicate::str::contains("--udp"));
+
+    Ok(())
+}
+
 #[cfg(target_os = "linux")]
 mod linux {
     use super::*;
@@ -78,7 +521,7 @@ mod linux {
 
         cmd.assert()
             .code(predicate::eq(3))
-            .stdout(predicate::str::contains("DecodeV4Error"));
+            .stdout(predicate::str::contains("insufficient permissions"));
 
         Ok(())
     }